@@ -0,0 +1,245 @@
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+
+use crate::rollback::RollbackApp;
+use crate::simulation::TickSet;
+
+pub struct SpriteAnimationPlugin;
+
+/// Sprite animations are advanced in this set, once per gameplay tick so that anything waiting on
+/// a clip to finish stays deterministic. Whatever sets `AnimationConditions` should run before it.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SpriteAnimationSet;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PlaybackMode {
+    Loop,
+    /// Plays through once. If a transition still chooses it after it has finished, it plays
+    /// through again.
+    Once,
+    /// Plays through once and holds the last frame for as long as it's chosen.
+    Hold,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Frame {
+    /// Index into the entity's texture atlas.
+    pub index: usize,
+    /// Seconds this frame stays on screen.
+    pub duration: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Clip {
+    pub frames: Vec<Frame>,
+    pub mode: PlaybackMode,
+}
+
+impl Clip {
+    /// Seconds it takes to play through once.
+    pub fn duration(&self) -> f32 {
+        self.frames.iter().map(|frame| frame.duration).sum()
+    }
+}
+
+/// Chooses `clip` while the condition `when` holds, or always if there's no condition.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Transition {
+    pub clip: String,
+    pub when: Option<String>,
+}
+
+/// Every named clip available for one spritesheet, and how an entity picks between them.
+#[derive(Asset, TypePath, Clone, Debug, Default)]
+pub struct AnimationLibrary {
+    pub clips: HashMap<String, Clip>,
+    /// Tried in order against the entity's `AnimationConditions`, and the first that applies
+    /// picks the clip. Without any, the clip only changes through `SpriteAnimation::play`.
+    pub transitions: Vec<Transition>,
+}
+
+impl AnimationLibrary {
+    pub fn with_clip(mut self, name: &str, clip: Clip) -> Self {
+        self.clips.insert(name.to_string(), clip);
+        self
+    }
+
+    pub fn with_transitions(mut self, transitions: Vec<Transition>) -> Self {
+        self.transitions = transitions;
+        self
+    }
+}
+
+/// What gameplay says an entity is doing, such as walking or being hurt, for the transitions in
+/// its library to pick a clip from.
+#[derive(Component, Clone, Default, Debug)]
+pub struct AnimationConditions(HashSet<&'static str>);
+
+impl AnimationConditions {
+    pub fn set(&mut self, condition: &'static str, holds: bool) {
+        if holds {
+            self.0.insert(condition);
+        } else {
+            self.0.remove(condition);
+        }
+    }
+
+    pub fn holds(&self, condition: &str) -> bool {
+        self.0.contains(condition)
+    }
+}
+
+/// Plays clips from an `AnimationLibrary` on the entity's `TextureAtlasSprite`.
+#[derive(Component, Clone)]
+pub struct SpriteAnimation {
+    library: Handle<AnimationLibrary>,
+    clip: String,
+    frame: usize,
+    elapsed: f32,
+    finished: bool,
+}
+
+impl SpriteAnimation {
+    pub fn new(library: Handle<AnimationLibrary>, clip: &str) -> Self {
+        Self {
+            library,
+            clip: clip.to_string(),
+            frame: 0,
+            elapsed: 0.0,
+            finished: false,
+        }
+    }
+
+    /// Switches to `clip` from its first frame, unless it's already playing.
+    pub fn play(&mut self, clip: &str) {
+        if self.clip != clip {
+            self.clip = clip.to_string();
+            self.restart();
+        }
+    }
+
+    pub fn restart(&mut self) {
+        self.frame = 0;
+        self.elapsed = 0.0;
+        self.finished = false;
+    }
+
+    pub fn library(&self) -> &Handle<AnimationLibrary> {
+        &self.library
+    }
+
+    pub fn clip(&self) -> &str {
+        &self.clip
+    }
+
+    /// Whether a one-shot clip has reached its end. Looping clips never finish.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
+/// Sent when a one-shot clip plays its last frame through.
+#[derive(Event)]
+pub struct AnimationFinished {
+    pub entity: Entity,
+    pub clip: String,
+}
+
+fn apply_transitions(
+    libraries: Res<Assets<AnimationLibrary>>,
+    mut animation_query: Query<(&AnimationConditions, &mut SpriteAnimation)>,
+) {
+    for (conditions, mut animation) in animation_query.iter_mut() {
+        let Some(library) = libraries.get(&animation.library) else {
+            continue;
+        };
+        let Some(transition) = library.transitions.iter().find(|transition| {
+            transition
+                .when
+                .as_deref()
+                .map_or(true, |condition| conditions.holds(condition))
+        }) else {
+            continue;
+        };
+
+        let replay = animation.clip == transition.clip
+            && animation.finished
+            && library
+                .clips
+                .get(&transition.clip)
+                .is_some_and(|clip| clip.mode == PlaybackMode::Once);
+        if replay {
+            animation.restart();
+        } else {
+            animation.play(&transition.clip);
+        }
+    }
+}
+
+fn animate_sprites(
+    time: Res<Time>,
+    libraries: Res<Assets<AnimationLibrary>>,
+    mut finished_events: EventWriter<AnimationFinished>,
+    mut animation_query: Query<(Entity, &mut SpriteAnimation, &mut TextureAtlasSprite)>,
+) {
+    for (entity, mut animation, mut sprite) in animation_query.iter_mut() {
+        let Some(clip) = libraries
+            .get(&animation.library)
+            .and_then(|library| library.clips.get(&animation.clip))
+        else {
+            continue;
+        };
+
+        if clip.frames.is_empty() {
+            continue;
+        }
+
+        let animation = &mut *animation;
+        // The clip may have been shortened by a hot-reload.
+        animation.frame = animation.frame.min(clip.frames.len() - 1);
+
+        if !animation.finished {
+            animation.elapsed += time.delta_seconds();
+
+            loop {
+                let duration = clip.frames[animation.frame].duration;
+                if duration <= 0.0 || animation.elapsed < duration {
+                    break;
+                }
+                animation.elapsed -= duration;
+
+                if animation.frame + 1 < clip.frames.len() {
+                    animation.frame += 1;
+                } else if clip.mode == PlaybackMode::Loop {
+                    animation.frame = 0;
+                } else {
+                    animation.finished = true;
+                    finished_events.send(AnimationFinished {
+                        entity,
+                        clip: animation.clip.clone(),
+                    });
+                    break;
+                }
+            }
+        }
+
+        sprite.index = clip.frames[animation.frame].index;
+    }
+}
+
+impl Plugin for SpriteAnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<AnimationLibrary>()
+            .add_event::<AnimationFinished>()
+            .rollback_component::<SpriteAnimation>()
+            .rollback_component::<AnimationConditions>()
+            .configure_sets(FixedUpdate, SpriteAnimationSet.in_set(TickSet::Act))
+            .add_systems(
+                FixedUpdate,
+                (apply_transitions, animate_sprites)
+                    .chain()
+                    .in_set(SpriteAnimationSet),
+            );
+    }
+}
@@ -0,0 +1,244 @@
+use asefile::{AnimationDirection, AsepriteFile};
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AsyncReadExt, LoadContext};
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::utils::BoxedFuture;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::animation::{AnimationLibrary, Clip, Frame, PlaybackMode, Transition};
+
+pub struct AsepritePlugin;
+
+/// A spritesheet read straight from an `.aseprite` file.
+///
+/// Frames are laid out left to right in `atlas`, and every tag becomes a clip in `animations`.
+/// Both are labeled sub-assets (`#atlas` and `#animations`), so handles to them stay valid when
+/// the file is saved again in Aseprite.
+#[derive(Asset, TypePath, Debug)]
+pub struct Aseprite {
+    pub atlas: Handle<TextureAtlas>,
+    pub animations: Handle<AnimationLibrary>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct AsepriteSettings {
+    /// Tags that should play through once instead of looping.
+    pub play_once: Vec<String>,
+    /// Tags that should play through once and then hold their last frame.
+    pub hold: Vec<String>,
+    /// How sprites pick a tag to play, see `AnimationLibrary::transitions`.
+    pub transitions: Vec<Transition>,
+    /// Tags the art doesn't have yet, each played with the frames of another tag meanwhile.
+    pub stand_ins: Vec<(String, String)>,
+}
+
+impl AsepriteSettings {
+    pub fn with_play_once(mut self, tag: &str) -> Self {
+        self.play_once.push(tag.to_string());
+        self
+    }
+
+    pub fn with_hold(mut self, tag: &str) -> Self {
+        self.hold.push(tag.to_string());
+        self
+    }
+
+    /// Plays `tag` while `condition` holds, unless an earlier transition applies.
+    pub fn with_transition(mut self, tag: &str, condition: &str) -> Self {
+        self.transitions.push(Transition {
+            clip: tag.to_string(),
+            when: Some(condition.to_string()),
+        });
+        self
+    }
+
+    /// Uses the frames of `stand_in` for `tag` until the file has a `tag` of its own.
+    pub fn with_stand_in(mut self, tag: &str, stand_in: &str) -> Self {
+        self.stand_ins.push((tag.to_string(), stand_in.to_string()));
+        self
+    }
+
+    /// Plays `tag` when none of the transitions before it apply.
+    pub fn with_fallback(mut self, tag: &str) -> Self {
+        self.transitions.push(Transition {
+            clip: tag.to_string(),
+            when: None,
+        });
+        self
+    }
+}
+
+/// Starts loading the atlas and animations of an `.aseprite` file, for spawning sprites before
+/// the file itself has finished loading.
+pub fn load_aseprite(
+    asset_server: &AssetServer,
+    path: &str,
+    settings: AsepriteSettings,
+) -> (Handle<TextureAtlas>, Handle<AnimationLibrary>) {
+    let settings = move |loaded: &mut AsepriteSettings| *loaded = settings.clone();
+
+    (
+        asset_server.load_with_settings(format!("{path}#atlas"), settings.clone()),
+        asset_server.load_with_settings(format!("{path}#animations"), settings),
+    )
+}
+
+#[derive(Default)]
+struct AsepriteLoader;
+
+#[derive(Error, Debug)]
+enum AsepriteLoaderError {
+    #[error("Could not read aseprite file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse aseprite file: {0}")]
+    Parse(#[from] asefile::AsepriteParseError),
+}
+
+/// The frame indices a tag plays, in order.
+fn tag_frames(from: u32, to: u32, direction: AnimationDirection) -> Vec<u32> {
+    match direction {
+        AnimationDirection::Reverse => (from..=to).rev().collect(),
+        // There and back again, without showing the frames at either end twice.
+        AnimationDirection::PingPong => (from..=to).chain((from + 1..to).rev()).collect(),
+        _ => (from..=to).collect(),
+    }
+}
+
+impl AssetLoader for AsepriteLoader {
+    type Asset = Aseprite;
+    type Settings = AsepriteSettings;
+    type Error = AsepriteLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        settings: &'a Self::Settings,
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let file = AsepriteFile::read(bytes.as_slice())?;
+
+            let frame_width = file.width() as u32;
+            let frame_height = file.height() as u32;
+            let frame_count = file.num_frames();
+            let sheet_width = frame_width * frame_count;
+
+            // Flatten every frame's layers and copy them side by side into one row.
+            let mut data = vec![0; (sheet_width * frame_height * 4) as usize];
+            for frame_index in 0..frame_count {
+                let frame = file.frame(frame_index).image().into_raw();
+                let row_bytes = (frame_width * 4) as usize;
+
+                for (y, row) in frame.chunks_exact(row_bytes).enumerate() {
+                    let start = ((y as u32 * sheet_width + frame_index * frame_width) * 4) as usize;
+                    data[start..start + row_bytes].copy_from_slice(row);
+                }
+            }
+
+            let image = load_context.add_labeled_asset(
+                "image".to_string(),
+                Image::new(
+                    Extent3d {
+                        width: sheet_width,
+                        height: frame_height,
+                        depth_or_array_layers: 1,
+                    },
+                    TextureDimension::D2,
+                    data,
+                    TextureFormat::Rgba8UnormSrgb,
+                ),
+            );
+
+            let atlas = load_context.add_labeled_asset(
+                "atlas".to_string(),
+                TextureAtlas::from_grid(
+                    image,
+                    Vec2::new(frame_width as f32, frame_height as f32),
+                    frame_count as usize,
+                    1,
+                    None,
+                    None,
+                ),
+            );
+
+            let mode = |tag: &str| {
+                if settings.hold.iter().any(|name| name == tag) {
+                    PlaybackMode::Hold
+                } else if settings.play_once.iter().any(|name| name == tag) {
+                    PlaybackMode::Once
+                } else {
+                    PlaybackMode::Loop
+                }
+            };
+
+            let mut animations = AnimationLibrary::default();
+            for tag in (0..file.num_tags()).map(|index| file.tag(index)) {
+                let frames =
+                    tag_frames(tag.from_frame(), tag.to_frame(), tag.animation_direction())
+                        .into_iter()
+                        .map(|index| Frame {
+                            index: index as usize,
+                            // Aseprite stores durations in milliseconds.
+                            duration: file.frame(index).duration() as f32 / 1000.0,
+                        })
+                        .collect();
+
+                let mode = mode(tag.name());
+                animations = animations.with_clip(tag.name(), Clip { frames, mode });
+            }
+
+            for (tag, stand_in) in settings.stand_ins.iter() {
+                if animations.clips.contains_key(tag) {
+                    continue;
+                }
+                let Some(frames) = animations
+                    .clips
+                    .get(stand_in)
+                    .map(|clip| clip.frames.clone())
+                else {
+                    warn!("No {stand_in} tag to stand in for {tag}");
+                    continue;
+                };
+
+                let mode = mode(tag);
+                animations = animations.with_clip(tag, Clip { frames, mode });
+            }
+
+            let animations = load_context.add_labeled_asset(
+                "animations".to_string(),
+                animations.with_transitions(settings.transitions.clone()),
+            );
+
+            Ok(Aseprite { atlas, animations })
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["aseprite", "ase"]
+    }
+}
+
+fn report_reloaded_spritesheets(
+    asset_server: Res<AssetServer>,
+    mut asset_events: EventReader<AssetEvent<Aseprite>>,
+) {
+    for event in asset_events.read() {
+        if let AssetEvent::Modified { id } = event {
+            if let Some(path) = asset_server.get_path(*id) {
+                info!("Reloaded spritesheet {path}");
+            }
+        }
+    }
+}
+
+impl Plugin for AsepritePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Aseprite>()
+            .init_asset_loader::<AsepriteLoader>()
+            .add_systems(Update, report_reloaded_spritesheets);
+    }
+}
@@ -0,0 +1,520 @@
+use std::collections::BTreeMap;
+use std::mem;
+
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::*;
+use leafwing_input_manager::user_input::InputKind;
+use serde::{Deserialize, Serialize};
+
+use crate::game_state::OpenPanels;
+use crate::mage::{LocalPlayers, Mage, MageActions, Player};
+use crate::player_input::Seating;
+use crate::settings::{load_ron, save_ron, SettingsDir};
+
+pub struct BindingsPlugin;
+
+const BINDINGS_FILE: &str = "bindings.ron";
+/// How many seats get a share of the keyboard: the first one, and the right half for the second.
+const KEYBOARD_SEATS: usize = 2;
+const MOVE_DEADZONE: f32 = 0.15;
+const AIM_DEADZONE: f32 = 0.3;
+
+/// Everything a player can rebind. Movement is split into the four virtual dpad directions.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub enum BindingSlot {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    SpellPrimary,
+    SpellSecondary,
+    SpellSlot1,
+    SpellSlot2,
+    SpellSlot3,
+    SpellSlot4,
+    SpellWheel,
+    ToggleAimMode,
+}
+
+impl BindingSlot {
+    const ALL: [BindingSlot; 12] = [
+        BindingSlot::MoveUp,
+        BindingSlot::MoveDown,
+        BindingSlot::MoveLeft,
+        BindingSlot::MoveRight,
+        BindingSlot::SpellPrimary,
+        BindingSlot::SpellSecondary,
+        BindingSlot::SpellSlot1,
+        BindingSlot::SpellSlot2,
+        BindingSlot::SpellSlot3,
+        BindingSlot::SpellSlot4,
+        BindingSlot::SpellWheel,
+        BindingSlot::ToggleAimMode,
+    ];
+
+    /// The action a button slot drives. Movement directions are combined into a dpad instead.
+    fn action(self) -> Option<MageActions> {
+        match self {
+            BindingSlot::MoveUp
+            | BindingSlot::MoveDown
+            | BindingSlot::MoveLeft
+            | BindingSlot::MoveRight => None,
+            BindingSlot::SpellPrimary => Some(MageActions::SpellPrimary),
+            BindingSlot::SpellSecondary => Some(MageActions::SpellSecondary),
+            BindingSlot::SpellSlot1 => Some(MageActions::SpellSlot1),
+            BindingSlot::SpellSlot2 => Some(MageActions::SpellSlot2),
+            BindingSlot::SpellSlot3 => Some(MageActions::SpellSlot3),
+            BindingSlot::SpellSlot4 => Some(MageActions::SpellSlot4),
+            BindingSlot::SpellWheel => Some(MageActions::SpellWheel),
+            BindingSlot::ToggleAimMode => Some(MageActions::ToggleAimMode),
+        }
+    }
+}
+
+/// The player's button bindings, saved to `bindings.ron` in the `SettingsDir` whenever they
+/// change.
+///
+/// Each slot holds at most one input per device kind (keyboard, mouse, gamepad button).
+/// The analog sticks aren't rebindable and are always added by [`KeyBindings::input_map`].
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+pub struct KeyBindings {
+    slots: BTreeMap<BindingSlot, Vec<InputKind>>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        use GamepadButtonType::*;
+        use InputKind::{GamepadButton, Keyboard, Mouse};
+
+        let slots = BTreeMap::from([
+            (
+                BindingSlot::MoveUp,
+                vec![Keyboard(KeyCode::W), GamepadButton(DPadUp)],
+            ),
+            (
+                BindingSlot::MoveDown,
+                vec![Keyboard(KeyCode::S), GamepadButton(DPadDown)],
+            ),
+            (
+                BindingSlot::MoveLeft,
+                vec![Keyboard(KeyCode::A), GamepadButton(DPadLeft)],
+            ),
+            (
+                BindingSlot::MoveRight,
+                vec![Keyboard(KeyCode::D), GamepadButton(DPadRight)],
+            ),
+            (
+                BindingSlot::SpellPrimary,
+                vec![
+                    Keyboard(KeyCode::Q),
+                    Mouse(MouseButton::Left),
+                    GamepadButton(RightTrigger2),
+                ],
+            ),
+            (
+                BindingSlot::SpellSecondary,
+                vec![
+                    Keyboard(KeyCode::E),
+                    Mouse(MouseButton::Right),
+                    GamepadButton(LeftTrigger2),
+                ],
+            ),
+            (BindingSlot::SpellSlot1, vec![Keyboard(KeyCode::Key1)]),
+            (BindingSlot::SpellSlot2, vec![Keyboard(KeyCode::Key2)]),
+            (BindingSlot::SpellSlot3, vec![Keyboard(KeyCode::Key3)]),
+            (BindingSlot::SpellSlot4, vec![Keyboard(KeyCode::Key4)]),
+            (
+                BindingSlot::SpellWheel,
+                vec![Keyboard(KeyCode::ShiftLeft), GamepadButton(LeftTrigger)],
+            ),
+            (
+                BindingSlot::ToggleAimMode,
+                vec![Keyboard(KeyCode::Tab), GamepadButton(Select)],
+            ),
+        ]);
+
+        Self { slots }
+    }
+}
+
+impl KeyBindings {
+    /// Reads the saved bindings, falling back to the defaults if there are none or they're invalid.
+    pub fn load(dir: &SettingsDir) -> Self {
+        let mut bindings: Self = load_ron(dir.file(BINDINGS_FILE)).unwrap_or_default();
+
+        // Slots added since the file was saved get their default bindings.
+        for (slot, inputs) in Self::default().slots {
+            bindings.slots.entry(slot).or_insert(inputs);
+        }
+        bindings
+    }
+
+    fn save(&self, dir: &SettingsDir) {
+        save_ron(dir.file(BINDINGS_FILE), self);
+    }
+
+    fn inputs(&self, slot: BindingSlot) -> &[InputKind] {
+        self.slots.get(&slot).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Binds `input` to `slot`, replacing the slot's previous input of the same device kind.
+    /// If another slot was already using `input`, it gets the replaced input instead.
+    pub fn rebind(&mut self, slot: BindingSlot, input: InputKind) {
+        let same_kind = |other: &InputKind| mem::discriminant(other) == mem::discriminant(&input);
+
+        let inputs = self.slots.entry(slot).or_default();
+        let displaced = inputs
+            .iter()
+            .position(same_kind)
+            .map(|index| inputs.remove(index));
+        inputs.push(input.clone());
+
+        for (&other_slot, other_inputs) in self.slots.iter_mut() {
+            if other_slot == slot {
+                continue;
+            }
+
+            if let Some(index) = other_inputs.iter().position(|other| *other == input) {
+                match &displaced {
+                    Some(displaced) => other_inputs[index] = displaced.clone(),
+                    None => {
+                        other_inputs.remove(index);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The input map for the player in `seat` when there are `seats` sharing the controls.
+    ///
+    /// The first seat gets these bindings with the mouse and keyboard, and the second gets the
+    /// right half of the keyboard. Gamepad bindings are shared, but each seat only listens to
+    /// its own gamepad, unless there's only the one seat. Seats without a share of the keyboard
+    /// are handed gamepads first, in the order they connected.
+    pub fn input_map(
+        &self,
+        seat: usize,
+        seats: usize,
+        gamepads: &Gamepads,
+    ) -> InputMap<MageActions> {
+        let right_half = right_keyboard_half();
+        let keyboard_slots = match seat {
+            0 => Some(&self.slots),
+            1 => Some(&right_half),
+            _ => None,
+        };
+
+        let gamepad = seat_gamepad(seat, seats, gamepads);
+        let gamepad_slots = (seats == 1 || gamepad.is_some()).then_some(&self.slots);
+
+        if keyboard_slots.is_none() && gamepad_slots.is_none() {
+            warn!(
+                "Player {} has nothing to play with until another gamepad connects",
+                seat + 1
+            );
+        }
+
+        let stick_deadzone = |radius| DeadZoneShape::Ellipse {
+            radius_x: radius,
+            radius_y: radius,
+        };
+
+        let mut input_map = InputMap::default();
+        if gamepad_slots.is_some() {
+            input_map
+                .insert(
+                    DualAxis::left_stick().with_deadzone(stick_deadzone(MOVE_DEADZONE)),
+                    MageActions::Move,
+                )
+                .insert(
+                    DualAxis::right_stick().with_deadzone(stick_deadzone(AIM_DEADZONE)),
+                    MageActions::Aim,
+                );
+        }
+
+        let is_gamepad = |input: &&InputKind| matches!(input, InputKind::GamepadButton(_));
+        for (slots, gamepad) in [(keyboard_slots, false), (gamepad_slots, true)] {
+            let Some(slots) = slots else {
+                continue;
+            };
+            let device_inputs = |slot| {
+                slots
+                    .get(&slot)
+                    .into_iter()
+                    .flatten()
+                    .filter(move |input| is_gamepad(input) == gamepad)
+            };
+
+            // One virtual dpad per device, since each needs an input for all four directions.
+            match (
+                device_inputs(BindingSlot::MoveUp).next(),
+                device_inputs(BindingSlot::MoveDown).next(),
+                device_inputs(BindingSlot::MoveLeft).next(),
+                device_inputs(BindingSlot::MoveRight).next(),
+            ) {
+                (Some(up), Some(down), Some(left), Some(right)) => {
+                    input_map.insert(
+                        VirtualDPad {
+                            up: up.clone(),
+                            down: down.clone(),
+                            left: left.clone(),
+                            right: right.clone(),
+                        },
+                        MageActions::Move,
+                    );
+                }
+                _ => warn!("Movement is missing a direction, so it can't be used on every device"),
+            }
+
+            for slot in BindingSlot::ALL {
+                let Some(action) = slot.action() else {
+                    continue;
+                };
+
+                for input in device_inputs(slot) {
+                    input_map.insert(input.clone(), action);
+                }
+            }
+        }
+
+        if let Some(gamepad) = gamepad.filter(|_| seats > 1) {
+            input_map.set_gamepad(gamepad);
+        }
+
+        input_map.build()
+    }
+}
+
+/// Which connected gamepad belongs to `seat`, if there are enough to go round.
+fn seat_gamepad(seat: usize, seats: usize, gamepads: &Gamepads) -> Option<Gamepad> {
+    let mut connected: Vec<Gamepad> = gamepads.iter().collect();
+    connected.sort_by_key(|gamepad| gamepad.id);
+
+    let mut handed_out = (KEYBOARD_SEATS..seats).chain(0..seats.min(KEYBOARD_SEATS));
+    let turn = handed_out.position(|other| other == seat)?;
+    connected.get(turn).copied()
+}
+
+/// The second player's share of the keyboard. It isn't rebindable, and has no mouse.
+fn right_keyboard_half() -> BTreeMap<BindingSlot, Vec<InputKind>> {
+    use InputKind::Keyboard;
+
+    BTreeMap::from([
+        (BindingSlot::MoveUp, vec![Keyboard(KeyCode::Up)]),
+        (BindingSlot::MoveDown, vec![Keyboard(KeyCode::Down)]),
+        (BindingSlot::MoveLeft, vec![Keyboard(KeyCode::Left)]),
+        (BindingSlot::MoveRight, vec![Keyboard(KeyCode::Right)]),
+        (BindingSlot::SpellPrimary, vec![Keyboard(KeyCode::Period)]),
+        (BindingSlot::SpellSecondary, vec![Keyboard(KeyCode::Slash)]),
+        (BindingSlot::SpellSlot1, vec![Keyboard(KeyCode::Numpad1)]),
+        (BindingSlot::SpellSlot2, vec![Keyboard(KeyCode::Numpad2)]),
+        (BindingSlot::SpellSlot3, vec![Keyboard(KeyCode::Numpad3)]),
+        (BindingSlot::SpellSlot4, vec![Keyboard(KeyCode::Numpad4)]),
+        (BindingSlot::SpellWheel, vec![Keyboard(KeyCode::ShiftRight)]),
+        (BindingSlot::ToggleAimMode, vec![Keyboard(KeyCode::Return)]),
+    ])
+}
+
+/// Which slot, if any, is waiting for the player to press its new input.
+#[derive(Resource, Default)]
+struct Rebinding {
+    capturing: Option<BindingSlot>,
+}
+
+#[derive(Component)]
+struct BindingsPanel;
+
+#[derive(Component)]
+struct BindingButton(BindingSlot);
+
+fn load_bindings(settings_dir: Res<SettingsDir>, mut bindings: ResMut<KeyBindings>) {
+    // Nothing has been rebound yet, so there's nothing to save back.
+    *bindings.bypass_change_detection() = KeyBindings::load(&settings_dir);
+}
+
+fn setup_bindings_panel(mut commands: Commands) {
+    commands
+        .spawn((
+            BindingsPanel,
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(8.0)),
+                    row_gap: Val::Px(4.0),
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.8).into(),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+        ))
+        .with_children(|panel| {
+            for slot in BindingSlot::ALL {
+                panel
+                    .spawn((BindingButton(slot), ButtonBundle::default()))
+                    .with_children(|button| {
+                        button.spawn(TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font_size: 14.0,
+                                ..default()
+                            },
+                        ));
+                    });
+            }
+        });
+}
+
+fn toggle_bindings_panel(
+    keyboard: Res<Input<KeyCode>>,
+    mut rebinding: ResMut<Rebinding>,
+    mut open_panels: ResMut<OpenPanels>,
+    mut panel_query: Query<&mut Visibility, With<BindingsPanel>>,
+) {
+    if !keyboard.just_pressed(KeyCode::F1) {
+        return;
+    }
+
+    for mut visibility in panel_query.iter_mut() {
+        let opening = *visibility == Visibility::Hidden;
+        *visibility = if opening {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+        open_panels.set("bindings", opening);
+        rebinding.capturing = None;
+    }
+}
+
+fn select_binding(
+    mut rebinding: ResMut<Rebinding>,
+    button_query: Query<(&Interaction, &BindingButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in button_query.iter() {
+        if *interaction == Interaction::Pressed {
+            rebinding.capturing = Some(button.0);
+        }
+    }
+}
+
+fn capture_binding(
+    keyboard: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut rebinding: ResMut<Rebinding>,
+    mut bindings: ResMut<KeyBindings>,
+) {
+    // The click that selected the slot shouldn't be captured as its new binding.
+    if rebinding.is_changed() {
+        return;
+    }
+
+    let Some(slot) = rebinding.capturing else {
+        return;
+    };
+
+    if keyboard.just_pressed(KeyCode::Escape) {
+        rebinding.capturing = None;
+        return;
+    }
+
+    let captured = keyboard
+        .get_just_pressed()
+        .next()
+        .map(|&key| InputKind::Keyboard(key))
+        .or_else(|| {
+            mouse
+                .get_just_pressed()
+                .next()
+                .map(|&button| InputKind::Mouse(button))
+        })
+        .or_else(|| {
+            gamepad_buttons
+                .get_just_pressed()
+                .next()
+                .map(|button| InputKind::GamepadButton(button.button_type))
+        });
+
+    if let Some(input) = captured {
+        bindings.rebind(slot, input);
+        rebinding.capturing = None;
+    }
+}
+
+/// Rebuilds every player's input map when the bindings change or a gamepad comes or goes.
+fn apply_bindings(
+    settings_dir: Option<Res<SettingsDir>>,
+    bindings: Res<KeyBindings>,
+    players: Res<LocalPlayers>,
+    seating: Res<Seating>,
+    gamepads: Res<Gamepads>,
+    mut input_map_query: Query<(&mut InputMap<MageActions>, &Player), With<Mage>>,
+) {
+    let rebound = bindings.is_changed() && !bindings.is_added();
+    if !rebound && !gamepads.is_changed() {
+        return;
+    }
+
+    let seats = seating.seats(players.0);
+    for (mut input_map, &player) in input_map_query.iter_mut() {
+        if let Some(seat) = seating.seat(player) {
+            *input_map = bindings.input_map(seat, seats, &gamepads);
+        }
+    }
+
+    if let (true, Some(dir)) = (rebound, settings_dir) {
+        bindings.save(&dir);
+    }
+}
+
+fn update_binding_labels(
+    bindings: Res<KeyBindings>,
+    rebinding: Res<Rebinding>,
+    button_query: Query<(&BindingButton, &Children)>,
+    mut text_query: Query<&mut Text>,
+) {
+    if !bindings.is_changed() && !rebinding.is_changed() {
+        return;
+    }
+
+    for (button, children) in button_query.iter() {
+        let label = if rebinding.capturing == Some(button.0) {
+            format!("{:?}: press an input (Esc to cancel)", button.0)
+        } else {
+            format!("{:?}: {:?}", button.0, bindings.inputs(button.0))
+        };
+
+        for &child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(child) {
+                text.sections[0].value = label.clone();
+            }
+        }
+    }
+}
+
+impl Plugin for BindingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<KeyBindings>()
+            .init_resource::<Rebinding>()
+            .add_systems(
+                Startup,
+                (
+                    load_bindings.run_if(resource_exists::<SettingsDir>()),
+                    setup_bindings_panel,
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    toggle_bindings_panel,
+                    select_binding,
+                    capture_binding,
+                    apply_bindings,
+                    update_binding_labels,
+                )
+                    .chain(),
+            );
+    }
+}
@@ -0,0 +1,206 @@
+use bevy::prelude::*;
+use bevy_xpbd_2d::prelude::*;
+
+use crate::animation::SpriteAnimation;
+use crate::aseprite::Aseprite;
+use crate::game_state::{GameState, InGame};
+use crate::health::DamageEvent;
+use crate::layers::Layer;
+use crate::rollback::{Rollback, RollbackApp, RollbackIds};
+use crate::simulation::{Interpolated, TickSet};
+
+pub struct BlastPlugin;
+
+/// Blasts are launched, stopped at walls and detonated in this set in `TickSet::React`, in that
+/// order. Other hits in `React` come after it, so damage always lines up the same way.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BlastSet;
+
+const BLAST_KNOCKBACK: f32 = 200.0;
+
+/// Sent by anything that wants a blast projectile in the world.
+#[derive(Event)]
+pub struct LaunchBlast {
+    pub owner: Entity,
+    pub origin: Vec2,
+    pub direction: Vec2,
+    pub speed: f32,
+    pub lifetime: f32,
+    pub sprite: Handle<Aseprite>,
+}
+
+/// Sent when a caster wants all of their in-flight blasts to explode.
+#[derive(Event)]
+pub struct DetonateBlasts {
+    pub owner: Entity,
+    pub radius: f32,
+    pub damage: f32,
+}
+
+/// Sent for every blast that explodes, after the explosion has been resolved.
+#[derive(Event)]
+pub struct BlastExploded {
+    pub owner: Entity,
+    pub position: Vec2,
+}
+
+#[derive(Component, Clone)]
+pub struct Blast {
+    pub owner: Entity,
+    lifetime: Timer,
+}
+
+fn launch_blasts(
+    mut commands: Commands,
+    mut launch_events: EventReader<LaunchBlast>,
+    mut rollback_ids: ResMut<RollbackIds>,
+    spritesheets: Res<Assets<Aseprite>>,
+) {
+    for launch in launch_events.read() {
+        let Some(spritesheet) = spritesheets.get(&launch.sprite) else {
+            warn!("Blast launched before its spritesheet finished loading");
+            continue;
+        };
+        let direction = launch.direction.normalize_or_zero();
+
+        commands.spawn((
+            Blast {
+                owner: launch.owner,
+                lifetime: Timer::from_seconds(launch.lifetime, TimerMode::Once),
+            },
+            InGame,
+            Interpolated::default(),
+            rollback_ids.next(),
+            SpriteAnimation::new(spritesheet.animations.clone(), "fly"),
+            SpriteSheetBundle {
+                texture_atlas: spritesheet.atlas.clone(),
+                // Sprite faces right, so rotate it to match the direction of travel.
+                transform: Transform::from_translation(launch.origin.extend(1.0))
+                    .with_rotation(Quat::from_rotation_z(direction.y.atan2(direction.x))),
+                ..default()
+            },
+            RigidBody::Kinematic,
+            Collider::ball(6.0),
+            Sensor,
+            // Passes through the caster, but still reports touching bats and walls.
+            CollisionLayers::new([Layer::PlayerProjectile], [Layer::Enemy, Layer::Wall]),
+            LinearVelocity(direction * launch.speed),
+        ));
+    }
+}
+
+fn expire_blasts(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut blast_query: Query<(Entity, &mut Blast)>,
+) {
+    for (entity, mut blast) in blast_query.iter_mut() {
+        blast.lifetime.tick(time.delta());
+
+        if blast.lifetime.finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Blasts stop where they hit a wall and wait there to be detonated.
+fn stop_blasts_at_walls(
+    mut collision_events: EventReader<CollisionStarted>,
+    layers_query: Query<&CollisionLayers>,
+    mut blast_query: Query<&mut LinearVelocity, With<Blast>>,
+) {
+    for CollisionStarted(entity1, entity2) in collision_events.read() {
+        for (blast, other) in [(*entity1, *entity2), (*entity2, *entity1)] {
+            let hit_wall = layers_query
+                .get(other)
+                .is_ok_and(|layers| layers.contains_group(Layer::Wall));
+
+            if let (true, Ok(mut velocity)) = (hit_wall, blast_query.get_mut(blast)) {
+                velocity.0 = Vec2::ZERO;
+            }
+        }
+    }
+}
+
+fn detonate_blasts(
+    mut commands: Commands,
+    mut detonate_events: EventReader<DetonateBlasts>,
+    mut exploded_events: EventWriter<BlastExploded>,
+    mut damage_events: EventWriter<DamageEvent>,
+    spatial_query: SpatialQuery,
+    blast_query: Query<(Entity, &Blast, &Position, &Rollback)>,
+    position_query: Query<&Position>,
+    rollback_query: Query<&Rollback>,
+    mut body_query: Query<(&RigidBody, &mut LinearVelocity)>,
+) {
+    // Damage goes out in the order blasts and targets were spawned, rather than the order they
+    // happen to sit in, which a rollback can reshuffle. What dies first gets the RNG first.
+    let mut blasts: Vec<_> = blast_query.iter().collect();
+    blasts.sort_by_key(|(.., rollback)| **rollback);
+
+    for detonate in detonate_events.read() {
+        for &(entity, blast, position, _) in blasts.iter() {
+            if blast.owner != detonate.owner {
+                continue;
+            }
+
+            // Only what can be hurt is caught up in it, so not the caster's side, walls or other
+            // blasts, this one included.
+            let blast_position = position.0;
+            let mut targets = spatial_query.shape_intersections(
+                &Collider::ball(detonate.radius),
+                blast_position,
+                0.0,
+                SpatialQueryFilter::new().with_masks([Layer::Enemy]),
+            );
+            targets.sort_by_key(|target| rollback_query.get(*target).ok().copied());
+
+            for target in targets {
+                let knockback = position_query
+                    .get(target)
+                    .map(|position| (position.0 - blast_position).normalize_or_zero())
+                    .unwrap_or_default()
+                    * BLAST_KNOCKBACK;
+
+                // Knockback is a velocity kick so that it doesn't depend on the target's mass.
+                if let Ok((rigid_body, mut velocity)) = body_query.get_mut(target) {
+                    if rigid_body.is_dynamic() {
+                        velocity.0 += knockback;
+                    }
+                }
+
+                damage_events.send(DamageEvent {
+                    target,
+                    amount: detonate.damage,
+                    source: Some(blast.owner),
+                });
+            }
+
+            exploded_events.send(BlastExploded {
+                owner: blast.owner,
+                position: blast_position,
+            });
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+impl Plugin for BlastPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<LaunchBlast>()
+            .add_event::<DetonateBlasts>()
+            .add_event::<BlastExploded>()
+            .rollback_component::<Blast>()
+            .configure_sets(FixedUpdate, BlastSet.in_set(TickSet::React))
+            .add_systems(
+                FixedUpdate,
+                (
+                    (launch_blasts, stop_blasts_at_walls, detonate_blasts)
+                        .chain()
+                        .in_set(BlastSet),
+                    expire_blasts.in_set(TickSet::Cleanup),
+                )
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
@@ -0,0 +1,127 @@
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+use rand::Rng;
+
+use crate::blast::BlastExploded;
+use crate::game_state::GameState;
+use crate::health::Damaged;
+use crate::level::LevelBounds;
+use crate::mage::Mage;
+use crate::simulation::InterpolationSet;
+
+pub struct CameraPlugin;
+
+/// How quickly the camera catches up with the mages. Higher is snappier.
+const FOLLOW_SHARPNESS: f32 = 8.0;
+/// Trauma lost per second, so a full-strength shake settles in about a second.
+const TRAUMA_DECAY: f32 = 1.2;
+const MAX_SHAKE_OFFSET: f32 = 8.0;
+const BLAST_TRAUMA: f32 = 0.35;
+const PLAYER_HIT_TRAUMA: f32 = 0.5;
+
+#[derive(Component, Default)]
+struct FollowCamera {
+    /// Where the camera is looking before shake and pixel snapping are applied.
+    focus: Vec2,
+    /// From 0.0 to 1.0. Shake grows with the square of this, so small knocks stay subtle.
+    trauma: f32,
+}
+
+impl FollowCamera {
+    fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).min(1.0);
+    }
+}
+
+fn camera_setup(mut commands: Commands) {
+    commands.spawn((Camera2dBundle::default(), FollowCamera::default()));
+}
+
+fn shake_on_impacts(
+    mut exploded_events: EventReader<BlastExploded>,
+    mut damaged_events: EventReader<Damaged>,
+    mage_query: Query<(), With<Mage>>,
+    mut camera_query: Query<&mut FollowCamera>,
+) {
+    let blasts = exploded_events.read().count();
+    let hits = damaged_events
+        .read()
+        .filter(|damaged| mage_query.contains(damaged.entity))
+        .count();
+    if blasts == 0 && hits == 0 {
+        return;
+    }
+
+    for mut camera in camera_query.iter_mut() {
+        camera.add_trauma(blasts as f32 * BLAST_TRAUMA + hits as f32 * PLAYER_HIT_TRAUMA);
+    }
+}
+
+fn follow_mage(
+    time: Res<Time>,
+    bounds: Option<Res<LevelBounds>>,
+    mage_query: Query<&Transform, (With<Mage>, Without<FollowCamera>)>,
+    mut camera_query: Query<(&mut FollowCamera, &mut Transform, &OrthographicProjection)>,
+) {
+    // Halfway between every mage still standing, so nobody walks off screen alone.
+    let mages = mage_query.iter().count();
+    if mages == 0 {
+        return;
+    }
+    let mage_center = mage_query
+        .iter()
+        .map(|transform| transform.translation.truncate())
+        .sum::<Vec2>()
+        / mages as f32;
+
+    let mut rng = rand::thread_rng();
+
+    for (mut camera, mut transform, projection) in camera_query.iter_mut() {
+        // Framerate-independent easing towards the mages.
+        let catch_up = 1.0 - (-FOLLOW_SHARPNESS * time.delta_seconds()).exp();
+        camera.focus = camera.focus.lerp(mage_center, catch_up);
+
+        // Keep the view inside the level, or centred on it along any axis it doesn't fill.
+        if let Some(bounds) = &bounds {
+            let half_view = projection.area.half_size();
+            let min = bounds.min + half_view;
+            let max = bounds.max - half_view;
+            camera.focus = Vec2::new(
+                if min.x <= max.x {
+                    camera.focus.x.clamp(min.x, max.x)
+                } else {
+                    bounds.center().x
+                },
+                if min.y <= max.y {
+                    camera.focus.y.clamp(min.y, max.y)
+                } else {
+                    bounds.center().y
+                },
+            );
+        }
+
+        let shake = camera.trauma * camera.trauma;
+        let offset = Vec2::new(rng.gen_range(-1.0..=1.0), rng.gen_range(-1.0..=1.0))
+            * MAX_SHAKE_OFFSET
+            * shake;
+        camera.trauma = (camera.trauma - TRAUMA_DECAY * time.delta_seconds()).max(0.0);
+
+        // Only ever land on whole pixels so the nearest-neighbour art doesn't shimmer.
+        let target = (camera.focus + offset).round();
+        transform.translation.x = target.x;
+        transform.translation.y = target.y;
+    }
+}
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, camera_setup).add_systems(
+            PostUpdate,
+            (shake_on_impacts, follow_mage)
+                .chain()
+                .after(InterpolationSet)
+                .before(TransformSystem::TransformPropagate)
+                .run_if(in_state(GameState::Playing)),
+        );
+    }
+}
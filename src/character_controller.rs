@@ -0,0 +1,118 @@
+use bevy::prelude::*;
+use bevy_xpbd_2d::plugins::collision::contact_query::contact;
+use bevy_xpbd_2d::prelude::*;
+
+use crate::game_state::GameState;
+use crate::layers::Layer;
+use crate::rollback::RollbackApp;
+use crate::simulation::TickSet;
+
+pub struct CharacterControllerPlugin;
+
+/// Gap kept between a character and whatever it's sliding along, so the next cast doesn't
+/// start out already touching it.
+const SKIN_WIDTH: f32 = 0.5;
+/// How many surfaces a single move can slide along before giving up, e.g. into a corner.
+const MAX_SLIDES: usize = 4;
+const MAX_HITS: u32 = 8;
+
+/// Moves a kinematic body at `velocity`, sliding along walls instead of passing through them
+/// the way kinematic bodies otherwise would. Only walls get in the way.
+///
+/// Nothing about it is specific to mages, but they're the only characters that walk so far.
+/// Bats fly as dynamic bodies, steered through their velocity, so they don't use it.
+#[derive(Component, Clone, Default)]
+pub struct CharacterController {
+    /// Pixels per second. Whatever drives the character sets this every tick.
+    pub velocity: Vec2,
+}
+
+fn move_characters(
+    time: Res<Time>,
+    spatial_query: SpatialQuery,
+    mut character_query: Query<(
+        Entity,
+        &CharacterController,
+        &Collider,
+        &mut Position,
+        &Rotation,
+    )>,
+    wall_query: Query<(&Collider, &Position, &Rotation), Without<CharacterController>>,
+) {
+    for (entity, controller, collider, mut position, rotation) in character_query.iter_mut() {
+        let filter = || {
+            SpatialQueryFilter::new()
+                .with_masks([Layer::Wall])
+                .without_entities([entity])
+        };
+
+        // Push back out of anything the character has ended up inside, like a wall that was
+        // hot-reloaded on top of it.
+        for overlapping in
+            spatial_query.shape_intersections(collider, position.0, rotation.as_radians(), filter())
+        {
+            let Ok((wall_collider, wall_position, wall_rotation)) = wall_query.get(overlapping)
+            else {
+                continue;
+            };
+
+            if let Ok(Some(penetration)) = contact(
+                collider,
+                *position,
+                *rotation,
+                wall_collider,
+                *wall_position,
+                *wall_rotation,
+                0.0,
+            ) {
+                position.0 -= penetration.normal1 * (penetration.penetration + SKIN_WIDTH);
+            }
+        }
+
+        let mut remaining = controller.velocity * time.delta_seconds();
+        for _ in 0..MAX_SLIDES {
+            let distance = remaining.length();
+            if distance <= f32::EPSILON {
+                break;
+            }
+            let direction = remaining / distance;
+
+            let hit = spatial_query
+                .shape_hits(
+                    collider,
+                    position.0,
+                    rotation.as_radians(),
+                    direction,
+                    distance + SKIN_WIDTH,
+                    MAX_HITS,
+                    true,
+                    filter(),
+                )
+                .into_iter()
+                .min_by(|a, b| a.time_of_impact.total_cmp(&b.time_of_impact));
+
+            let Some(hit) = hit else {
+                position.0 += remaining;
+                break;
+            };
+
+            let travel = (hit.time_of_impact - SKIN_WIDTH).max(0.0);
+            position.0 += direction * travel;
+
+            // The rest of the move carries on along the surface rather than into it.
+            remaining = direction * (distance - travel);
+            remaining -= hit.normal1 * remaining.dot(hit.normal1).min(0.0);
+        }
+    }
+}
+
+impl Plugin for CharacterControllerPlugin {
+    fn build(&self, app: &mut App) {
+        app.rollback_component::<CharacterController>().add_systems(
+            FixedUpdate,
+            move_characters
+                .in_set(TickSet::Move)
+                .run_if(in_state(GameState::Playing)),
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use bevy::prelude::*;
+use bevy_xpbd_2d::prelude::*;
+use rand::seq::IteratorRandom;
+use rand::Rng;
+
+use crate::animation::{AnimationLibrary, SpriteAnimation};
+use crate::aseprite::{load_aseprite, AsepriteSettings};
+use crate::game_state::{GameState, InGame, StartRun};
+use crate::health::{Died, Faction, Health, Hitbox, Hurtbox};
+use crate::layers::Layer;
+use crate::level::EnemySpawnPoint;
+use crate::mage::Mage;
+use crate::mana::ManaPickupBundle;
+use crate::prelude::TILE_SIZE;
+use crate::rollback::{Rollback, RollbackApp, RollbackIds};
+use crate::simulation::{GameRng, Interpolated, TickSet};
+
+pub struct EnemyPlugin;
+
+const BAT_SPEED: f32 = 40.0;
+const BAT_STEERING: f32 = 4.0;
+const BAT_SIGHT_RANGE: f32 = TILE_SIZE * 6.0;
+const BAT_HEALTH: f32 = 2.0;
+const BAT_CONTACT_DAMAGE: f32 = 1.0;
+const MAX_BATS: usize = 8;
+const MANA_DROP_CHANCE: f64 = 0.35;
+const MANA_DROP_AMOUNT: f32 = 3.0;
+
+#[derive(Component, Clone)]
+pub struct Bat {
+    wander_direction: Vec2,
+    wander_timer: Timer,
+}
+
+#[derive(Resource, Clone)]
+struct BatSpawner {
+    texture_atlas: Handle<TextureAtlas>,
+    animations: Handle<AnimationLibrary>,
+    spawn_timer: Timer,
+}
+
+fn setup_bat_spawner(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let (texture_atlas, animations) =
+        load_aseprite(&asset_server, "bat.aseprite", AsepriteSettings::default());
+
+    commands.insert_resource(BatSpawner {
+        texture_atlas,
+        animations,
+        spawn_timer: Timer::from_seconds(3.0, TimerMode::Repeating),
+    });
+}
+
+fn spawn_bats(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut rng: ResMut<GameRng>,
+    mut rollback_ids: ResMut<RollbackIds>,
+    mut spawner: ResMut<BatSpawner>,
+    bat_query: Query<(), With<Bat>>,
+    spawn_point_query: Query<&GlobalTransform, With<EnemySpawnPoint>>,
+) {
+    spawner.spawn_timer.tick(time.delta());
+    if !spawner.spawn_timer.just_finished() || bat_query.iter().count() >= MAX_BATS {
+        return;
+    }
+
+    // Nothing to spawn from until the level is in.
+    let Some(spawn_point) = spawn_point_query.iter().choose(&mut **rng) else {
+        return;
+    };
+    let spawn_position = spawn_point.translation().truncate();
+
+    commands.spawn((
+        Bat {
+            wander_direction: Vec2::ZERO,
+            wander_timer: Timer::from_seconds(1.5, TimerMode::Repeating),
+        },
+        InGame,
+        Interpolated::default(),
+        rollback_ids.next(),
+        SpriteAnimation::new(spawner.animations.clone(), "fly"),
+        Health::new(BAT_HEALTH),
+        Hitbox {
+            damage: BAT_CONTACT_DAMAGE,
+            faction: Faction::Enemy,
+        },
+        Hurtbox {
+            faction: Faction::Enemy,
+        },
+        SpriteSheetBundle {
+            texture_atlas: spawner.texture_atlas.clone(),
+            transform: Transform::from_translation(spawn_position.extend(0.5)),
+            ..default()
+        },
+        RigidBody::Dynamic,
+        Collider::ball(8.0),
+        CollisionLayers::new(
+            [Layer::Enemy],
+            [
+                Layer::Player,
+                Layer::Enemy,
+                Layer::PlayerProjectile,
+                Layer::Wall,
+            ],
+        ),
+        GravityScale(0.0),
+        LockedAxes::ROTATION_LOCKED,
+        LinearVelocity::default(),
+    ));
+}
+
+fn move_bats(
+    time: Res<Time>,
+    mut rng: ResMut<GameRng>,
+    mage_query: Query<&Position, With<Mage>>,
+    mut bat_query: Query<(&mut Bat, &Position, &mut LinearVelocity, &Rollback), Without<Mage>>,
+) {
+    // Bats take turns with the RNG in the order they spawned, which a rollback can't reshuffle.
+    let mut bats: Vec<_> = bat_query.iter_mut().collect();
+    bats.sort_by_key(|(.., rollback)| **rollback);
+
+    for (mut bat, position, mut velocity, _) in bats {
+        let position = position.0;
+
+        let nearest_mage = mage_query
+            .iter()
+            .map(|mage_position| mage_position.0)
+            .min_by(|a, b| a.distance(position).total_cmp(&b.distance(position)));
+
+        let desired_direction = match nearest_mage {
+            Some(mage_position) if mage_position.distance(position) <= BAT_SIGHT_RANGE => {
+                (mage_position - position).normalize_or_zero()
+            }
+            _ => {
+                bat.wander_timer.tick(time.delta());
+                if bat.wander_timer.just_finished() || bat.wander_direction == Vec2::ZERO {
+                    // Drift back towards the middle of the arena when no mage is in sight.
+                    let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+                    bat.wander_direction = (Vec2::from_angle(angle)
+                        - position.normalize_or_zero() * 0.5)
+                        .normalize_or_zero();
+                }
+                bat.wander_direction
+            }
+        };
+
+        // Steer rather than snap so knockback from blasts isn't immediately cancelled out.
+        let steering = (BAT_STEERING * time.delta_seconds()).min(1.0);
+        velocity.0 = velocity.0.lerp(desired_direction * BAT_SPEED, steering);
+    }
+}
+
+fn flip_bats(mut bat_query: Query<(&mut TextureAtlasSprite, &LinearVelocity), With<Bat>>) {
+    for (mut sprite, velocity) in bat_query.iter_mut() {
+        if velocity.x != 0.0 {
+            sprite.flip_x = velocity.x < 0.0;
+        }
+    }
+}
+
+fn despawn_dead_bats(
+    mut commands: Commands,
+    mut rng: ResMut<GameRng>,
+    mut rollback_ids: ResMut<RollbackIds>,
+    mut died_events: EventReader<Died>,
+    bat_query: Query<(&Position, &Rollback), With<Bat>>,
+) {
+    // Same as in `move_bats`: events come in whatever order the bats sit in, which a rollback
+    // can reshuffle, so they take turns with the RNG in the order they spawned instead.
+    let mut dead: Vec<_> = died_events
+        .read()
+        .filter_map(|died| {
+            let (position, rollback) = bat_query.get(died.entity).ok()?;
+            Some((*rollback, died.entity, position.0))
+        })
+        .collect();
+    dead.sort_by_key(|(rollback, ..)| *rollback);
+
+    for (_, entity, position) in dead {
+        if rng.gen_bool(MANA_DROP_CHANCE) {
+            commands.spawn((
+                ManaPickupBundle::new(position, MANA_DROP_AMOUNT),
+                rollback_ids.next(),
+            ));
+        }
+        commands.entity(entity).despawn();
+    }
+}
+
+impl Plugin for EnemyPlugin {
+    fn build(&self, app: &mut App) {
+        // A fresh spawner every run, so the first bat doesn't arrive early after a restart.
+        app.add_systems(StartRun, setup_bat_spawner)
+            .rollback_component::<Bat>()
+            .rollback_resource::<BatSpawner>()
+            .add_systems(
+                FixedUpdate,
+                (
+                    (spawn_bats, move_bats).chain().in_set(TickSet::Act),
+                    despawn_dead_bats.in_set(TickSet::Cleanup),
+                )
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(Update, flip_bats.run_if(in_state(GameState::Playing)));
+    }
+}
@@ -0,0 +1,312 @@
+use bevy::app::AppExit;
+use bevy::ecs::schedule::ScheduleLabel;
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use bevy_xpbd_2d::prelude::*;
+use leafwing_input_manager::prelude::*;
+
+use crate::mage::{LocalPlayers, MageActions};
+use crate::player_input::{offline, Seating};
+use crate::rollback::RollbackApp;
+use crate::simulation::TickSet;
+
+pub struct GameStatePlugin;
+
+#[derive(States, Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum GameState {
+    #[default]
+    MainMenu,
+    Playing,
+    Paused,
+    GameOver,
+}
+
+/// Runs once at the start of every run, whether from the title screen or after a game over.
+/// Gameplay entities should be spawned here rather than in `Startup`.
+#[derive(ScheduleLabel, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct StartRun;
+
+/// Sent from the tick that loses the run. Offline the game over screen follows straight away,
+/// while online it waits until no other machine's input can still change what happened.
+#[derive(Event)]
+pub struct RunLost;
+
+/// Marks everything that belongs to the current run, so it can be cleared away when it ends.
+#[derive(Component, Clone)]
+pub struct InGame;
+
+/// The settings panels open over the game. Gameplay actions are off while any of them is, so
+/// clicking around a panel doesn't cast spells.
+#[derive(Resource, Default, Debug)]
+pub struct OpenPanels(HashSet<&'static str>);
+
+impl OpenPanels {
+    pub fn set(&mut self, panel: &'static str, open: bool) {
+        if open {
+            self.0.insert(panel);
+        } else {
+            self.0.remove(panel);
+        }
+    }
+
+    pub fn any(&self) -> bool {
+        !self.0.is_empty()
+    }
+}
+
+/// Marks the UI of the screen shown for a particular state.
+#[derive(Component)]
+struct Menu;
+
+#[derive(Component, Clone, Copy)]
+enum MenuButton {
+    Play,
+    Players,
+    Resume,
+    Restart,
+    QuitToTitle,
+    Quit,
+}
+
+impl MenuButton {
+    fn label(self) -> &'static str {
+        match self {
+            MenuButton::Play => "Play",
+            MenuButton::Players => "Players",
+            MenuButton::Resume => "Resume",
+            MenuButton::Restart => "Try again",
+            MenuButton::QuitToTitle => "Quit to title",
+            MenuButton::Quit => "Quit",
+        }
+    }
+}
+
+fn spawn_menu(commands: &mut Commands, title: &str, buttons: &[MenuButton]) {
+    commands
+        .spawn((
+            Menu,
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(8.0),
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+                ..default()
+            },
+        ))
+        .with_children(|menu| {
+            menu.spawn(TextBundle::from_section(
+                title,
+                TextStyle {
+                    font_size: 32.0,
+                    ..default()
+                },
+            ));
+
+            for &button in buttons {
+                menu.spawn((
+                    button,
+                    ButtonBundle {
+                        style: Style {
+                            padding: UiRect::axes(Val::Px(12.0), Val::Px(4.0)),
+                            ..default()
+                        },
+                        background_color: Color::DARK_GRAY.into(),
+                        ..default()
+                    },
+                ))
+                .with_children(|button_node| {
+                    button_node.spawn(TextBundle::from_section(
+                        button.label(),
+                        TextStyle {
+                            font_size: 16.0,
+                            ..default()
+                        },
+                    ));
+                });
+            }
+        });
+}
+
+fn show_main_menu(mut commands: Commands) {
+    spawn_menu(
+        &mut commands,
+        "Magic Mania",
+        &[MenuButton::Play, MenuButton::Players, MenuButton::Quit],
+    );
+}
+
+fn show_pause_menu(mut commands: Commands) {
+    spawn_menu(
+        &mut commands,
+        "Paused",
+        &[MenuButton::Resume, MenuButton::QuitToTitle],
+    );
+}
+
+fn show_game_over(mut commands: Commands, seating: Res<Seating>) {
+    // Only the first run of an online session is kept in sync.
+    let buttons: &[MenuButton] = if seating.is_online() {
+        &[MenuButton::QuitToTitle]
+    } else {
+        &[MenuButton::Restart, MenuButton::QuitToTitle]
+    };
+    spawn_menu(&mut commands, "Game Over", buttons);
+}
+
+fn end_lost_runs(
+    mut lost_events: EventReader<RunLost>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if lost_events.read().count() > 0 {
+        next_state.set(GameState::GameOver);
+    }
+}
+
+fn despawn_with<T: Component>(mut commands: Commands, query: Query<Entity, With<T>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn press_menu_buttons(
+    mut next_state: ResMut<NextState<GameState>>,
+    mut players: ResMut<LocalPlayers>,
+    mut exit_events: EventWriter<AppExit>,
+    button_query: Query<(&Interaction, &MenuButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in button_query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        match button {
+            MenuButton::Play | MenuButton::Resume | MenuButton::Restart => {
+                next_state.set(GameState::Playing);
+            }
+            MenuButton::Players => *players = players.next(),
+            MenuButton::QuitToTitle => next_state.set(GameState::MainMenu),
+            MenuButton::Quit => exit_events.send(AppExit),
+        }
+    }
+}
+
+/// Shows how many players the next run starts with on the button that changes it.
+fn label_players_button(
+    players: Res<LocalPlayers>,
+    button_query: Query<(Ref<MenuButton>, &Children)>,
+    mut text_query: Query<&mut Text>,
+) {
+    for (button, children) in button_query.iter() {
+        if !matches!(*button, MenuButton::Players) {
+            continue;
+        }
+        if !button.is_added() && !players.is_changed() {
+            continue;
+        }
+
+        let mut texts = text_query.iter_many_mut(children);
+        while let Some(mut text) = texts.fetch_next() {
+            text.sections[0].value = format!("Players: {}", players.0);
+        }
+    }
+}
+
+fn handle_escape(
+    keyboard: Res<Input<KeyCode>>,
+    open_panels: Res<OpenPanels>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut exit_events: EventWriter<AppExit>,
+) {
+    // Leave Escape alone while the bindings (F1) or loadout (F2) panel is open. The bindings
+    // panel cancels capturing a new binding with it.
+    if !keyboard.just_pressed(KeyCode::Escape) || open_panels.any() {
+        return;
+    }
+
+    match state.get() {
+        GameState::MainMenu => exit_events.send(AppExit),
+        GameState::Playing => next_state.set(GameState::Paused),
+        GameState::Paused => next_state.set(GameState::Playing),
+        GameState::GameOver => next_state.set(GameState::MainMenu),
+    }
+}
+
+fn disable_actions_under_panels(
+    open_panels: Res<OpenPanels>,
+    mut toggle_actions: ResMut<ToggleActions<MageActions>>,
+) {
+    if open_panels.is_changed() {
+        toggle_actions.enabled = !open_panels.any();
+    }
+}
+
+fn start_run(world: &mut World) {
+    world.run_schedule(StartRun);
+}
+
+/// Freezes timers, animations and physics while gameplay isn't running.
+fn pause_time(mut time: ResMut<Time<Virtual>>, mut physics_time: ResMut<Time<Physics>>) {
+    time.pause();
+    physics_time.pause();
+}
+
+fn unpause_time(mut time: ResMut<Time<Virtual>>, mut physics_time: ResMut<Time<Physics>>) {
+    time.unpause();
+    physics_time.unpause();
+}
+
+impl Plugin for GameStatePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<GameState>()
+            .init_schedule(StartRun)
+            .init_resource::<OpenPanels>()
+            .add_event::<RunLost>()
+            .rollback_component::<InGame>()
+            .add_systems(OnEnter(GameState::MainMenu), show_main_menu)
+            .add_systems(OnEnter(GameState::Paused), show_pause_menu)
+            .add_systems(OnEnter(GameState::GameOver), show_game_over)
+            .add_systems(
+                OnTransition {
+                    from: GameState::MainMenu,
+                    to: GameState::Playing,
+                },
+                start_run,
+            )
+            .add_systems(
+                OnTransition {
+                    from: GameState::GameOver,
+                    to: GameState::Playing,
+                },
+                start_run,
+            )
+            // Runs are torn down when they're left behind for good, so the game over screen can
+            // still show the final moments behind it.
+            .add_systems(OnEnter(GameState::MainMenu), despawn_with::<InGame>)
+            .add_systems(OnExit(GameState::GameOver), despawn_with::<InGame>)
+            .add_systems(OnEnter(GameState::Playing), unpause_time)
+            .add_systems(OnExit(GameState::Playing), pause_time)
+            .add_systems(OnExit(GameState::MainMenu), despawn_with::<Menu>)
+            .add_systems(OnExit(GameState::Paused), despawn_with::<Menu>)
+            .add_systems(OnExit(GameState::GameOver), despawn_with::<Menu>)
+            // Online, the session ends runs once they're confirmed instead.
+            .add_systems(
+                FixedUpdate,
+                end_lost_runs.after(TickSet::Cleanup).run_if(offline),
+            )
+            .add_systems(
+                Update,
+                (
+                    (press_menu_buttons, label_players_button).chain(),
+                    handle_escape,
+                ),
+            )
+            .add_systems(PostUpdate, disable_actions_under_panels);
+    }
+}
@@ -0,0 +1,196 @@
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use bevy_xpbd_2d::prelude::*;
+
+use crate::blast::BlastSet;
+use crate::game_state::GameState;
+use crate::rollback::RollbackApp;
+use crate::simulation::TickSet;
+
+pub struct HealthPlugin;
+
+#[derive(Component, Clone)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+    /// How long the entity ignores further damage after being hit.
+    pub invulnerability: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self {
+            current: max,
+            max,
+            invulnerability: 0.0,
+        }
+    }
+
+    pub fn with_invulnerability(mut self, seconds: f32) -> Self {
+        self.invulnerability = seconds;
+        self
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Faction {
+    Player,
+    Enemy,
+}
+
+/// Deals damage to any hurtbox of another faction it starts touching.
+#[derive(Component, Clone)]
+pub struct Hitbox {
+    pub damage: f32,
+    pub faction: Faction,
+}
+
+/// Marks an entity with `Health` as able to be damaged by hitboxes.
+#[derive(Component, Clone)]
+pub struct Hurtbox {
+    pub faction: Faction,
+}
+
+#[derive(Component, Clone, Deref, DerefMut)]
+pub struct Invulnerable(Timer);
+
+#[derive(Event)]
+pub struct DamageEvent {
+    pub target: Entity,
+    pub amount: f32,
+    pub source: Option<Entity>,
+}
+
+/// Sent for every `DamageEvent` that actually took health away, after invulnerability has been
+/// taken into account.
+#[derive(Event)]
+pub struct Damaged {
+    pub entity: Entity,
+    pub amount: f32,
+}
+
+/// Sent once when an entity's health first drops to zero.
+#[derive(Event)]
+pub struct Died {
+    pub entity: Entity,
+}
+
+fn detect_hits(
+    mut collision_events: EventReader<CollisionStarted>,
+    mut damage_events: EventWriter<DamageEvent>,
+    hitbox_query: Query<&Hitbox>,
+    hurtbox_query: Query<&Hurtbox>,
+) {
+    for CollisionStarted(first, second) in collision_events.read() {
+        for (attacker, target) in [(*first, *second), (*second, *first)] {
+            let (Ok(hitbox), Ok(hurtbox)) = (hitbox_query.get(attacker), hurtbox_query.get(target))
+            else {
+                continue;
+            };
+
+            if hitbox.faction != hurtbox.faction {
+                damage_events.send(DamageEvent {
+                    target,
+                    amount: hitbox.damage,
+                    source: Some(attacker),
+                });
+            }
+        }
+    }
+}
+
+fn apply_damage(
+    mut commands: Commands,
+    mut damage_events: EventReader<DamageEvent>,
+    mut damaged_events: EventWriter<Damaged>,
+    mut died_events: EventWriter<Died>,
+    mut health_query: Query<&mut Health, Without<Invulnerable>>,
+) {
+    // `Invulnerable` only shows up once commands are applied, so anything hit earlier this
+    // tick has to be turned away here.
+    let mut hit = HashSet::new();
+
+    for damage in damage_events.read() {
+        let Ok(mut health) = health_query.get_mut(damage.target) else {
+            continue;
+        };
+
+        if health.is_dead() || hit.contains(&damage.target) {
+            continue;
+        }
+
+        let before = health.current;
+        health.current = (health.current - damage.amount).max(0.0);
+        damaged_events.send(Damaged {
+            entity: damage.target,
+            amount: before - health.current,
+        });
+
+        if health.is_dead() {
+            died_events.send(Died {
+                entity: damage.target,
+            });
+        } else if health.invulnerability > 0.0 {
+            hit.insert(damage.target);
+            commands
+                .entity(damage.target)
+                .insert(Invulnerable(Timer::from_seconds(
+                    health.invulnerability,
+                    TimerMode::Once,
+                )));
+        }
+    }
+}
+
+fn tick_invulnerability(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut invulnerable_query: Query<(Entity, &mut Invulnerable, Option<&mut TextureAtlasSprite>)>,
+) {
+    for (entity, mut invulnerable, sprite) in invulnerable_query.iter_mut() {
+        invulnerable.tick(time.delta());
+
+        let alpha = if invulnerable.finished() {
+            commands.entity(entity).remove::<Invulnerable>();
+            1.0
+        } else {
+            // Blink while invulnerable so it's obvious the hit registered.
+            let visible = (invulnerable.elapsed_secs() * 10.0) as u32 % 2 == 0;
+            if visible {
+                1.0
+            } else {
+                0.3
+            }
+        };
+
+        if let Some(mut sprite) = sprite {
+            sprite.color.set_a(alpha);
+        }
+    }
+}
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DamageEvent>()
+            .add_event::<Damaged>()
+            .add_event::<Died>()
+            .rollback_component::<Health>()
+            .rollback_component::<Hitbox>()
+            .rollback_component::<Hurtbox>()
+            .rollback_component::<Invulnerable>()
+            .add_systems(
+                FixedUpdate,
+                (
+                    detect_hits.in_set(TickSet::React).after(BlastSet),
+                    (apply_damage, tick_invulnerability)
+                        .chain()
+                        .in_set(TickSet::Damage),
+                )
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
@@ -0,0 +1,13 @@
+use bevy_xpbd_2d::prelude::*;
+
+/// What each body is, for deciding what it collides with. Every body sets its `CollisionLayers`
+/// from these when it's spawned.
+#[derive(PhysicsLayer, Clone, Copy, Debug)]
+pub enum Layer {
+    Player,
+    Enemy,
+    PlayerProjectile,
+    EnemyProjectile,
+    Wall,
+    Pickup,
+}
@@ -0,0 +1,314 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AsyncReadExt, LoadContext};
+use bevy::prelude::*;
+use bevy::utils::BoxedFuture;
+use bevy_xpbd_2d::prelude::*;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::game_state::{GameState, InGame};
+use crate::layers::Layer;
+use crate::prelude::TILE_SIZE;
+
+pub struct LevelPlugin;
+
+const LEVEL_PATH: &str = "levels/arena.level.ron";
+const FLOOR_COLOR: Color = Color::rgb(0.12, 0.1, 0.16);
+const WALL_COLOR: Color = Color::rgb(0.35, 0.3, 0.45);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Tile {
+    Floor,
+    Wall,
+}
+
+/// A grid of tiles loaded from a `.level.ron` file, where each row is a string with one
+/// character per tile: `#` for walls, `.` for floor and `B` for floor that bats spawn from.
+#[derive(Asset, TypePath, Debug)]
+pub struct Level {
+    width: u32,
+    height: u32,
+    tiles: Vec<Tile>,
+    enemy_spawns: Vec<UVec2>,
+}
+
+impl Level {
+    /// Row 0 is the top of the level, matching the order rows are written in the file.
+    pub fn tile(&self, x: u32, y: u32) -> Tile {
+        self.tiles[(y * self.width + x) as usize]
+    }
+
+    /// World position of a tile's centre. The level is centred on the origin.
+    pub fn tile_center(&self, x: u32, y: u32) -> Vec2 {
+        Vec2::new(
+            x as f32 - (self.width - 1) as f32 / 2.0,
+            (self.height - 1) as f32 / 2.0 - y as f32,
+        ) * TILE_SIZE
+    }
+
+    /// The area the level covers in the world.
+    pub fn bounds(&self) -> Rect {
+        Rect::from_center_size(
+            Vec2::ZERO,
+            Vec2::new(self.width as f32, self.height as f32) * TILE_SIZE,
+        )
+    }
+
+    /// Covers every wall tile with as few rectangles as it can, by growing each rectangle
+    /// right along its row and then down while the rows below match.
+    pub fn merged_walls(&self) -> Vec<URect> {
+        let mut covered = vec![false; self.tiles.len()];
+        let is_open_wall = |covered: &[bool], x: u32, y: u32| {
+            self.tile(x, y) == Tile::Wall && !covered[(y * self.width + x) as usize]
+        };
+
+        let mut walls = Vec::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if !is_open_wall(&covered, x, y) {
+                    continue;
+                }
+
+                let mut max_x = x + 1;
+                while max_x < self.width && is_open_wall(&covered, max_x, y) {
+                    max_x += 1;
+                }
+
+                let mut max_y = y + 1;
+                while max_y < self.height
+                    && (x..max_x).all(|column| is_open_wall(&covered, column, max_y))
+                {
+                    max_y += 1;
+                }
+
+                for covered_y in y..max_y {
+                    for covered_x in x..max_x {
+                        covered[(covered_y * self.width + covered_x) as usize] = true;
+                    }
+                }
+                walls.push(URect::new(x, y, max_x, max_y));
+            }
+        }
+        walls
+    }
+}
+
+/// The on-disk layout of a `.level.ron` file.
+#[derive(Deserialize)]
+struct LevelRon {
+    tiles: Vec<String>,
+}
+
+#[derive(Default)]
+struct LevelLoader;
+
+#[derive(Error, Debug)]
+enum LevelLoaderError {
+    #[error("Could not read level: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse level: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("Level has no tiles")]
+    Empty,
+    #[error("Row {row} is {found} tiles wide, but the first row is {expected}")]
+    RaggedRow {
+        row: usize,
+        found: usize,
+        expected: usize,
+    },
+    #[error("Unknown tile {tile:?} at row {row}, column {column}")]
+    UnknownTile {
+        tile: char,
+        row: usize,
+        column: usize,
+    },
+}
+
+impl AssetLoader for LevelLoader {
+    type Asset = Level;
+    type Settings = ();
+    type Error = LevelLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let ron: LevelRon = ron::de::from_bytes(&bytes)?;
+
+            let width = ron.tiles.first().map_or(0, |row| row.chars().count());
+            if width == 0 {
+                return Err(LevelLoaderError::Empty);
+            }
+
+            let mut tiles = Vec::new();
+            let mut enemy_spawns = Vec::new();
+            for (row, line) in ron.tiles.iter().enumerate() {
+                let found = line.chars().count();
+                if found != width {
+                    return Err(LevelLoaderError::RaggedRow {
+                        row,
+                        found,
+                        expected: width,
+                    });
+                }
+
+                for (column, tile) in line.chars().enumerate() {
+                    tiles.push(match tile {
+                        '#' => Tile::Wall,
+                        '.' => Tile::Floor,
+                        'B' => {
+                            enemy_spawns.push(UVec2::new(column as u32, row as u32));
+                            Tile::Floor
+                        }
+                        _ => return Err(LevelLoaderError::UnknownTile { tile, row, column }),
+                    });
+                }
+            }
+
+            Ok(Level {
+                width: width as u32,
+                height: ron.tiles.len() as u32,
+                tiles,
+                enemy_spawns,
+            })
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level.ron"]
+    }
+}
+
+#[derive(Resource)]
+struct CurrentLevel(Handle<Level>);
+
+/// The root of the spawned level. Tiles, walls and spawn points are its children.
+#[derive(Component)]
+struct Arena;
+
+/// The area covered by the current level, for keeping the camera inside it.
+#[derive(Resource, Deref)]
+pub struct LevelBounds(Rect);
+
+/// Somewhere inside the arena that enemies can appear.
+#[derive(Component)]
+pub struct EnemySpawnPoint;
+
+fn load_level(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(CurrentLevel(asset_server.load(LEVEL_PATH)));
+}
+
+/// Clears away the arena when its file changes, so `spawn_level` builds it again.
+fn reload_level(
+    mut commands: Commands,
+    current_level: Res<CurrentLevel>,
+    mut asset_events: EventReader<AssetEvent<Level>>,
+    arena_query: Query<Entity, With<Arena>>,
+) {
+    let modified = asset_events
+        .read()
+        .any(|event| matches!(event, AssetEvent::Modified { id } if *id == current_level.0.id()));
+
+    if modified {
+        for entity in arena_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        info!("Reloaded level {LEVEL_PATH}");
+    }
+}
+
+fn spawn_level(
+    mut commands: Commands,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+    arena_query: Query<(), With<Arena>>,
+) {
+    if !arena_query.is_empty() {
+        return;
+    }
+
+    let Some(level) = levels.get(&current_level.0) else {
+        return;
+    };
+
+    commands.insert_resource(LevelBounds(level.bounds()));
+    commands
+        .spawn((Arena, InGame, SpatialBundle::default()))
+        .with_children(|arena| {
+            for y in 0..level.height {
+                for x in 0..level.width {
+                    let color = match level.tile(x, y) {
+                        Tile::Floor => FLOOR_COLOR,
+                        Tile::Wall => WALL_COLOR,
+                    };
+
+                    arena.spawn(SpriteBundle {
+                        sprite: Sprite {
+                            color,
+                            custom_size: Some(Vec2::splat(TILE_SIZE)),
+                            ..default()
+                        },
+                        transform: Transform::from_translation(
+                            level.tile_center(x, y).extend(-1.0),
+                        ),
+                        ..default()
+                    });
+                }
+            }
+
+            for wall in level.merged_walls() {
+                // Halfway between the centres of the first and last tiles the wall covers.
+                let center = (level.tile_center(wall.min.x, wall.min.y)
+                    + level.tile_center(wall.max.x - 1, wall.max.y - 1))
+                    / 2.0;
+                let size = wall.size().as_vec2() * TILE_SIZE;
+
+                arena.spawn((
+                    RigidBody::Static,
+                    Collider::cuboid(size.x, size.y),
+                    CollisionLayers::new(
+                        [Layer::Wall],
+                        [
+                            Layer::Player,
+                            Layer::Enemy,
+                            Layer::PlayerProjectile,
+                            Layer::EnemyProjectile,
+                        ],
+                    ),
+                    TransformBundle::from_transform(Transform::from_translation(
+                        center.extend(0.0),
+                    )),
+                ));
+            }
+
+            for spawn in level.enemy_spawns.iter() {
+                arena.spawn((
+                    EnemySpawnPoint,
+                    TransformBundle::from_transform(Transform::from_translation(
+                        level.tile_center(spawn.x, spawn.y).extend(0.0),
+                    )),
+                ));
+            }
+        });
+}
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Level>()
+            .init_asset_loader::<LevelLoader>()
+            .add_systems(Startup, load_level)
+            .add_systems(
+                Update,
+                (
+                    reload_level,
+                    spawn_level.run_if(in_state(GameState::Playing)),
+                )
+                    .chain(),
+            );
+    }
+}
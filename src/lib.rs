@@ -0,0 +1,85 @@
+pub mod animation;
+pub mod aseprite;
+pub mod bindings;
+pub mod blast;
+pub mod camera;
+pub mod character_controller;
+pub mod enemy;
+pub mod game_state;
+pub mod health;
+pub mod layers;
+pub mod level;
+pub mod loadout;
+pub mod mage;
+pub mod mana;
+pub mod netcode;
+pub mod player_input;
+pub mod replay;
+pub mod rollback;
+pub mod settings;
+pub mod simulation;
+pub mod spell;
+pub mod spell_wheel;
+
+use bevy::app::PluginGroupBuilder;
+use bevy::prelude::*;
+
+use animation::SpriteAnimationPlugin;
+use aseprite::AsepritePlugin;
+use bindings::BindingsPlugin;
+use blast::BlastPlugin;
+use camera::CameraPlugin;
+use character_controller::CharacterControllerPlugin;
+use enemy::EnemyPlugin;
+use game_state::GameStatePlugin;
+use health::HealthPlugin;
+use level::LevelPlugin;
+use loadout::LoadoutPlugin;
+use mage::MagePlugin;
+use mana::ManaPlugin;
+use netcode::NetcodePlugin;
+use player_input::PlayerInputPlugin;
+use replay::ReplayPlugin;
+use rollback::RollbackPlugin;
+use simulation::SimulationPlugin;
+use spell::SpellPlugin;
+use spell_wheel::SpellWheelPlugin;
+
+pub mod prelude {
+    pub const TILE_SIZE: f32 = 32.0;
+    pub const WINDOW_HEIGHT: f32 = TILE_SIZE * 15.0;
+    pub const WINDOW_WIDTH: f32 = TILE_SIZE * 15.0;
+}
+
+/// Everything that makes up the game, without the window, renderer or physics.
+///
+/// The game runs with either `DefaultPlugins` or, for tests, `MinimalPlugins` plus the
+/// handful of headless plugins the gameplay relies on. Physics has to be stepped in
+/// `FixedUpdate` alongside the gameplay, with `PhysicsPlugins::new(FixedUpdate)`.
+pub struct GamePlugins;
+
+impl PluginGroup for GamePlugins {
+    fn build(self) -> PluginGroupBuilder {
+        PluginGroupBuilder::start::<Self>()
+            .add(GameStatePlugin)
+            .add(SimulationPlugin)
+            .add(RollbackPlugin)
+            .add(PlayerInputPlugin)
+            .add(ReplayPlugin::default())
+            .add(NetcodePlugin)
+            .add(CameraPlugin)
+            .add(AsepritePlugin)
+            .add(SpriteAnimationPlugin)
+            .add(LevelPlugin)
+            .add(CharacterControllerPlugin)
+            .add(MagePlugin)
+            .add(SpellPlugin)
+            .add(BlastPlugin)
+            .add(EnemyPlugin)
+            .add(HealthPlugin)
+            .add(ManaPlugin)
+            .add(BindingsPlugin)
+            .add(LoadoutPlugin)
+            .add(SpellWheelPlugin)
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game_state::OpenPanels;
+use crate::mage::{Mage, MageActions, Player, SpellSlotMap};
+use crate::player_input::Seating;
+use crate::settings::{load_ron, save_ron, SettingsDir};
+use crate::spell::SpellDefinition;
+
+pub struct LoadoutPlugin;
+
+const PROFILE_FILE: &str = "profile.ron";
+
+/// Everything about the player that outlives a single run, saved to `profile.ron` in the
+/// `SettingsDir`.
+///
+/// Spells are stored by asset path so the profile stays valid across hot-reloads and restarts.
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+pub struct PlayerProfile {
+    pub unlocked_spells: Vec<String>,
+    pub loadout: BTreeMap<MageActions, String>,
+}
+
+impl Default for PlayerProfile {
+    fn default() -> Self {
+        let launch = "spells/blast_launch.spell.ron".to_string();
+        let activate = "spells/blast_activate.spell.ron".to_string();
+
+        Self {
+            unlocked_spells: vec![launch.clone(), activate.clone()],
+            // The numbered slots start out with every unlocked spell, so the spell wheel has
+            // something to swap in.
+            loadout: BTreeMap::from([
+                (MageActions::SpellPrimary, launch.clone()),
+                (MageActions::SpellSecondary, activate.clone()),
+                (MageActions::SpellSlot1, launch),
+                (MageActions::SpellSlot2, activate),
+            ]),
+        }
+    }
+}
+
+impl PlayerProfile {
+    /// Reads the saved profile, falling back to a fresh one if there is none or it's invalid.
+    pub fn load(dir: &SettingsDir) -> Self {
+        load_ron(dir.file(PROFILE_FILE)).unwrap_or_default()
+    }
+
+    fn save(&self, dir: &SettingsDir) {
+        save_ron(dir.file(PROFILE_FILE), self);
+    }
+
+    pub fn spell_slot_map(&self, asset_server: &AssetServer) -> SpellSlotMap {
+        let mut spell_slot_map = SpellSlotMap::default();
+        for (&slot, path) in self.loadout.iter() {
+            spell_slot_map.insert(slot, asset_server.load(path.clone()));
+        }
+        spell_slot_map
+    }
+
+    /// The display name of the spell in `slot`, or its path while the definition is loading.
+    pub fn spell_name(
+        &self,
+        slot: MageActions,
+        asset_server: &AssetServer,
+        spells: &Assets<SpellDefinition>,
+    ) -> Option<String> {
+        self.loadout.get(&slot).map(|path| {
+            let handle: Handle<SpellDefinition> = asset_server.load(path.clone());
+            spells
+                .get(&handle)
+                .map_or_else(|| path.clone(), |spell| spell.name.clone())
+        })
+    }
+
+    /// Exchanges the spells in two slots, as long as `from` has a spell to give.
+    pub fn swap_slots(&mut self, from: MageActions, to: MageActions) {
+        let Some(from_spell) = self.loadout.remove(&from) else {
+            return;
+        };
+
+        if let Some(to_spell) = self.loadout.insert(to, from_spell) {
+            self.loadout.insert(from, to_spell);
+        }
+    }
+
+    /// Moves `slot` on to the next unlocked spell, wrapping around at the end.
+    fn cycle_slot(&mut self, slot: MageActions) {
+        if self.unlocked_spells.is_empty() {
+            return;
+        }
+
+        let next_index = self
+            .loadout
+            .get(&slot)
+            .and_then(|current| self.unlocked_spells.iter().position(|path| path == current))
+            .map_or(0, |index| (index + 1) % self.unlocked_spells.len());
+
+        self.loadout
+            .insert(slot, self.unlocked_spells[next_index].clone());
+    }
+}
+
+#[derive(Component)]
+struct LoadoutPanel;
+
+#[derive(Component)]
+struct LoadoutButton(MageActions);
+
+fn load_profile(settings_dir: Res<SettingsDir>, mut profile: ResMut<PlayerProfile>) {
+    // Nothing has changed yet, so there's nothing to save back.
+    *profile.bypass_change_detection() = PlayerProfile::load(&settings_dir);
+}
+
+fn setup_loadout_panel(mut commands: Commands) {
+    commands
+        .spawn((
+            LoadoutPanel,
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    right: Val::Px(0.0),
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(8.0)),
+                    row_gap: Val::Px(4.0),
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.8).into(),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+        ))
+        .with_children(|panel| {
+            for slot in MageActions::SPELL_SLOTS {
+                panel
+                    .spawn((LoadoutButton(slot), ButtonBundle::default()))
+                    .with_children(|button| {
+                        button.spawn(TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font_size: 14.0,
+                                ..default()
+                            },
+                        ));
+                    });
+            }
+        });
+}
+
+fn toggle_loadout_panel(
+    keyboard: Res<Input<KeyCode>>,
+    mut open_panels: ResMut<OpenPanels>,
+    mut panel_query: Query<&mut Visibility, With<LoadoutPanel>>,
+) {
+    if !keyboard.just_pressed(KeyCode::F2) {
+        return;
+    }
+
+    for mut visibility in panel_query.iter_mut() {
+        let opening = *visibility == Visibility::Hidden;
+        *visibility = if opening {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+        open_panels.set("loadout", opening);
+    }
+}
+
+fn assign_spell(
+    mut profile: ResMut<PlayerProfile>,
+    button_query: Query<(&Interaction, &LoadoutButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in button_query.iter() {
+        if *interaction == Interaction::Pressed {
+            profile.cycle_slot(button.0);
+        }
+    }
+}
+
+/// Only the first player's mage follows the profile. The others start from it, but their
+/// changes last just the one run. Online, changes wait for the next offline run.
+fn apply_loadout(
+    settings_dir: Option<Res<SettingsDir>>,
+    asset_server: Res<AssetServer>,
+    profile: Res<PlayerProfile>,
+    seating: Res<Seating>,
+    mut spell_slot_map_query: Query<(&mut SpellSlotMap, &Player), With<Mage>>,
+) {
+    if !profile.is_changed() || profile.is_added() {
+        return;
+    }
+
+    for (mut spell_slot_map, player) in spell_slot_map_query.iter_mut() {
+        if !seating.is_online() && seating.seat(*player) == Some(0) {
+            *spell_slot_map = profile.spell_slot_map(&asset_server);
+        }
+    }
+    if let Some(dir) = settings_dir {
+        profile.save(&dir);
+    }
+}
+
+fn update_loadout_labels(
+    asset_server: Res<AssetServer>,
+    spells: Res<Assets<SpellDefinition>>,
+    profile: Res<PlayerProfile>,
+    panel_query: Query<&Visibility, With<LoadoutPanel>>,
+    button_query: Query<(&LoadoutButton, &Children)>,
+    mut text_query: Query<&mut Text>,
+) {
+    // Spell names only show up once their definitions have loaded, so refresh while visible.
+    if panel_query
+        .iter()
+        .all(|visibility| *visibility == Visibility::Hidden)
+    {
+        return;
+    }
+
+    for (button, children) in button_query.iter() {
+        let spell_name = profile.spell_name(button.0, &asset_server, &spells);
+        let label = format!(
+            "{:?}: {}",
+            button.0,
+            spell_name.as_deref().unwrap_or("(empty)")
+        );
+
+        for &child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(child) {
+                text.sections[0].value = label.clone();
+            }
+        }
+    }
+}
+
+impl Plugin for LoadoutPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlayerProfile>()
+            .add_systems(
+                Startup,
+                (
+                    load_profile.run_if(resource_exists::<SettingsDir>()),
+                    setup_loadout_panel,
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    toggle_loadout_panel,
+                    assign_spell,
+                    apply_loadout,
+                    update_loadout_labels,
+                )
+                    .chain(),
+            );
+    }
+}
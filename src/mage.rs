@@ -1,223 +1,558 @@
-use std::iter::Cycle;
-use std::ops::RangeInclusive;
-
 use bevy::sprite::Anchor;
 use bevy::{prelude::*, utils::HashMap};
 use bevy_xpbd_2d::prelude::*;
 use leafwing_input_manager::plugin::InputManagerSystem;
 use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
 
+use crate::animation::{
+    AnimationConditions, AnimationFinished, SpriteAnimation, SpriteAnimationSet,
+};
+use crate::aseprite::{load_aseprite, AsepriteSettings};
+use crate::bindings::KeyBindings;
+use crate::blast::{DetonateBlasts, LaunchBlast};
+use crate::character_controller::CharacterController;
+use crate::game_state::{GameState, InGame, RunLost, StartRun};
+use crate::health::{Faction, Health, Hurtbox, Invulnerable};
+use crate::layers::Layer;
+use crate::loadout::PlayerProfile;
+use crate::mana::Mana;
+use crate::player_input::{PendingInput, PlayerInput, Seating};
 use crate::prelude::TILE_SIZE;
+use crate::rollback::{Rollback, RollbackApp};
+use crate::simulation::{Interpolated, TickSet};
+use crate::spell::{CastFailReason, CastFailed, Spell, SpellCooldowns, SpellDefinition};
 
 pub struct MagePlugin;
 
-#[derive(Component)]
-struct Mage {
+/// Mages aim, cast and move in this set in `TickSet::Act`. Whatever a cast depends on, such as
+/// mana and cooldowns, is brought up to date before it.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MageSet;
+
+pub const MAGE_SPEED: f32 = 10.0;
+/// How many players can share one screen.
+pub const MAX_PLAYERS: usize = 4;
+/// Tells the players' mages apart. The first player keeps the sprite's own colours.
+const PLAYER_TINTS: [Color; MAX_PLAYERS] = [
+    Color::WHITE,
+    Color::rgb(1.0, 0.6, 0.6),
+    Color::rgb(0.6, 1.0, 0.6),
+    Color::rgb(0.6, 0.8, 1.0),
+];
+
+/// Which local player controls a mage, counting from 0.
+#[derive(Component, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Player(pub usize);
+
+/// How many mages the next run starts with, from 1 to `MAX_PLAYERS`.
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug, Deref)]
+pub struct LocalPlayers(pub usize);
+
+impl Default for LocalPlayers {
+    fn default() -> Self {
+        Self(1)
+    }
+}
+
+impl LocalPlayers {
+    /// One more player, wrapping back round to one after `MAX_PLAYERS`.
+    pub fn next(self) -> Self {
+        Self(self.0 % MAX_PLAYERS + 1)
+    }
+}
+
+#[derive(Component, Clone)]
+pub struct Mage {
     firing_spell: bool,
     is_walking: bool,
-    spell_cooldown: Timer,
 }
 
-#[derive(Actionlike, PartialEq, Eq, Clone, Debug, Hash, Copy, Reflect)]
-enum MageActions {
-    Up,
-    Down,
-    Left,
-    Right,
+/// The direction the mage is facing, which is also where spells are aimed.
+#[derive(Component, Clone, Deref, DerefMut)]
+pub struct Aim(Vec2);
+
+/// Whether the mage aims where it walks or twin-stick style with the mouse cursor or right stick.
+#[derive(Component, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum AimMode {
+    Movement,
+    Cursor,
+    Stick,
+}
+
+#[derive(
+    Actionlike,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Clone,
+    Debug,
+    Hash,
+    Copy,
+    Reflect,
+    Serialize,
+    Deserialize,
+)]
+pub enum MageActions {
+    Move,
+    Aim,
     SpellPrimary,
     SpellSecondary,
+    /// The numbered slots, on keys 1 to 4. There are always exactly four: actions are a fixed
+    /// set, so more slots means more variants here, in `BindingSlot` and in the default bindings.
+    SpellSlot1,
+    SpellSlot2,
+    SpellSlot3,
+    SpellSlot4,
+    SpellWheel,
+    ToggleAimMode,
 }
 
-#[derive(Actionlike, PartialEq, Eq, Clone, Debug, Hash, Copy, Reflect)]
-enum Spell {
-    BlastLaunch,
-    BlastActivate,
-}
+impl MageActions {
+    /// The actions that cast whichever spell is assigned to them in the `SpellSlotMap`.
+    pub const SPELL_SLOTS: [MageActions; 6] = [
+        MageActions::SpellPrimary,
+        MageActions::SpellSecondary,
+        MageActions::SpellSlot1,
+        MageActions::SpellSlot2,
+        MageActions::SpellSlot3,
+        MageActions::SpellSlot4,
+    ];
 
-#[derive(Component, Debug, Default, Deref, DerefMut)]
-struct SpellSlotMap {
-    map: HashMap<MageActions, Spell>,
+    /// The numbered slots the spell wheel can swap into the primary slot, which is all four.
+    pub const WHEEL_SLOTS: [MageActions; 4] = [
+        MageActions::SpellSlot1,
+        MageActions::SpellSlot2,
+        MageActions::SpellSlot3,
+        MageActions::SpellSlot4,
+    ];
 }
 
-#[derive(Component)]
-struct RepeatingAnimation {
-    next_frame_index: Cycle<RangeInclusive<i32>>,
-    frame_timer: Timer,
+#[derive(Component, Clone, Debug, Default, Deref, DerefMut)]
+pub struct SpellSlotMap {
+    map: HashMap<MageActions, Handle<SpellDefinition>>,
 }
 
 #[derive(Bundle)]
 struct MageBundle {
     mage: Mage,
-    walk_animation: RepeatingAnimation,
+    player: Player,
+    aim: Aim,
+    aim_mode: AimMode,
+    animation: SpriteAnimation,
+    animation_conditions: AnimationConditions,
     slot_input_map: InputMap<MageActions>,
     slot_action_state: ActionState<MageActions>,
-    spell_action_state: ActionState<Spell>,
+    input: PlayerInput,
+    pending_input: PendingInput,
     spell_slot_map: SpellSlotMap,
+    spell_cooldowns: SpellCooldowns,
+    mana: Mana,
+    health: Health,
+    hurtbox: Hurtbox,
+    controller: CharacterController,
+    interpolated: Interpolated,
+    rollback: Rollback,
+    in_game: InGame,
 }
 
 fn setup_mage(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    bindings: Res<KeyBindings>,
+    profile: Res<PlayerProfile>,
+    players: Res<LocalPlayers>,
+    seating: Res<Seating>,
+    gamepads: Res<Gamepads>,
 ) {
-    use KeyCode::*;
-
-    let mage_spritesheet = asset_server.load("mage.png");
-    let mage_texture_atlas = TextureAtlas::from_grid(
-        mage_spritesheet,
-        Vec2::new(TILE_SIZE, TILE_SIZE),
-        3,
-        1,
-        None,
-        None,
+    let (texture_atlas, animations) = load_aseprite(
+        &asset_server,
+        "mage.aseprite",
+        AsepriteSettings::default()
+            // Placeholders until there's art for them.
+            .with_stand_in("hurt", "idle")
+            .with_stand_in("die", "idle")
+            .with_play_once("cast")
+            .with_hold("die")
+            .with_transition("die", "dead")
+            .with_transition("cast", "casting")
+            .with_transition("hurt", "hurt")
+            .with_transition("walk", "walking")
+            .with_fallback("idle"),
     );
 
-    let mut spell_slot_map = SpellSlotMap::default();
-    spell_slot_map.insert(MageActions::SpellPrimary, Spell::BlastLaunch);
-    spell_slot_map.insert(MageActions::SpellSecondary, Spell::BlastActivate);
+    // Loadouts aren't sent over the network, so online everyone starts with the same one.
+    let default_profile = PlayerProfile::default();
+    let profile = if seating.is_online() {
+        &default_profile
+    } else {
+        &*profile
+    };
+    let seats = seating.seats(players.0);
 
-    // Animation is already on the 0 frame, so start iterating at 1.
-    let mut walk_animation_frames = (0..=1).cycle();
-    walk_animation_frames.next();
+    for index in 0..players.0 {
+        let player = Player(index);
+        let seat = seating.seat(player);
 
-    // Move sprite up so the collider is at the bottom.
-    let mut sprite = TextureAtlasSprite::new(0);
-    sprite.anchor = Anchor::Custom(Vec2::new(0.0, -0.25));
+        // Move sprite up so the collider is at the bottom.
+        let mut sprite = TextureAtlasSprite::new(0);
+        sprite.anchor = Anchor::Custom(Vec2::new(0.0, -0.25));
+        sprite.color = PLAYER_TINTS[index];
 
-    commands.spawn((
-        MageBundle {
-            mage: Mage {
-                firing_spell: false,
-                is_walking: false,
-                spell_cooldown: Timer::from_seconds(0.5, TimerMode::Once),
+        // Side by side in the middle of the arena.
+        let offset = (index as f32 - (players.0 - 1) as f32 / 2.0) * TILE_SIZE * 1.5;
+
+        commands.spawn((
+            MageBundle {
+                mage: Mage {
+                    firing_spell: false,
+                    is_walking: false,
+                },
+                player,
+                aim: Aim(Vec2::X),
+                // Only the first player here has the mouse.
+                aim_mode: if seat == Some(0) {
+                    AimMode::Cursor
+                } else {
+                    AimMode::Movement
+                },
+                animation: SpriteAnimation::new(animations.clone(), "idle"),
+                animation_conditions: AnimationConditions::default(),
+                // Players elsewhere get their actions from the network instead.
+                slot_input_map: seat.map_or_else(InputMap::default, |seat| {
+                    bindings.input_map(seat, seats, &gamepads)
+                }),
+                slot_action_state: ActionState::default(),
+                input: PlayerInput::default(),
+                pending_input: PendingInput::default(),
+                spell_slot_map: profile.spell_slot_map(&asset_server),
+                spell_cooldowns: SpellCooldowns::default(),
+                mana: Mana::new(10.0, 1.0),
+                health: Health::new(5.0).with_invulnerability(1.0),
+                hurtbox: Hurtbox {
+                    faction: Faction::Player,
+                },
+                controller: CharacterController::default(),
+                interpolated: Interpolated::default(),
+                rollback: Rollback::player(player),
+                in_game: InGame,
             },
-            walk_animation: RepeatingAnimation {
-                next_frame_index: walk_animation_frames,
-                frame_timer: Timer::from_seconds(0.5, TimerMode::Repeating),
+            SpriteSheetBundle {
+                sprite,
+                texture_atlas: texture_atlas.clone(),
+                transform: Transform::from_xyz(offset, 0.0, 0.0),
+                ..default()
             },
-            slot_input_map: InputMap::new([
-                (Q, MageActions::SpellPrimary),
-                (E, MageActions::SpellSecondary),
-                (W, MageActions::Up),
-                (A, MageActions::Left),
-                (S, MageActions::Down),
-                (D, MageActions::Right),
-            ])
-            .build(),
-            slot_action_state: ActionState::default(),
-            spell_action_state: ActionState::default(),
-            spell_slot_map,
-        },
-        SpriteSheetBundle {
-            sprite,
-            texture_atlas: texture_atlases.add(mage_texture_atlas),
-            ..default()
-        },
-        RigidBody::Kinematic,
-        Collider::ball(8.0),
-        LinearVelocity::default(),
-    ));
-}
-
-fn copy_action_state(
-    mut query: Query<(
-        &ActionState<MageActions>,
-        &mut ActionState<Spell>,
+            RigidBody::Kinematic,
+            Collider::ball(8.0),
+            CollisionLayers::new(
+                [Layer::Player],
+                [
+                    Layer::Enemy,
+                    Layer::EnemyProjectile,
+                    Layer::Wall,
+                    Layer::Pickup,
+                ],
+            ),
+        ));
+    }
+}
+
+fn use_spell(
+    asset_server: Res<AssetServer>,
+    spells: Res<Assets<SpellDefinition>>,
+    mut launch_events: EventWriter<LaunchBlast>,
+    mut detonate_events: EventWriter<DetonateBlasts>,
+    mut cast_failed_events: EventWriter<CastFailed>,
+    mut mage_query: Query<(
+        Entity,
+        &PlayerInput,
         &SpellSlotMap,
+        &mut SpellCooldowns,
+        &mut Mana,
+        &mut Mage,
+        &Aim,
+        &Position,
+        &Health,
     )>,
 ) {
-    for (slot_state, mut spell_state, spell_slot_map) in query.iter_mut() {
-        for slot in MageActions::variants() {
-            if let Some(&matching_ability) = spell_slot_map.get(&slot) {
-                // This copies the `ActionData` between the ActionStates,
-                // including information about how long the buttons have been pressed or released
-                spell_state.set_action_data(matching_ability, slot_state.action_data(slot).clone());
+    // Mages only have a `Position` from their first tick of physics onwards.
+    for (entity, input, spell_slot_map, mut cooldowns, mut mana, mut mage, aim, position, health) in
+        mage_query.iter_mut()
+    {
+        if health.is_dead() {
+            continue;
+        }
+
+        for slot in MageActions::SPELL_SLOTS {
+            if !input.just_pressed(slot) {
+                continue;
+            }
+
+            let Some(handle) = spell_slot_map.get(&slot) else {
+                continue;
+            };
+            let Some(spell) = spells.get(handle) else {
+                continue;
+            };
+
+            if spell.effect == Spell::BlastLaunch {
+                // Launching needs a free hand, detonating is allowed mid-cast.
+                if mage.firing_spell {
+                    continue;
+                }
+                // A spell with nothing to launch mustn't cost anything.
+                let Some(sprite) = &spell.sprite else {
+                    warn!("Spell {} has no sprite to launch", spell.name);
+                    continue;
+                };
+                if !asset_server.is_loaded_with_dependencies(sprite.id()) {
+                    continue;
+                }
             }
+
+            let failure = if !cooldowns.is_ready(handle.id()) {
+                Some(CastFailReason::OnCooldown)
+            } else if !mana.try_spend(spell.mana_cost) {
+                Some(CastFailReason::NotEnoughMana)
+            } else {
+                None
+            };
+
+            if let Some(reason) = failure {
+                cast_failed_events.send(CastFailed {
+                    caster: entity,
+                    spell: handle.clone(),
+                    reason,
+                });
+                continue;
+            }
+
+            match spell.effect {
+                Spell::BlastActivate => {
+                    detonate_events.send(DetonateBlasts {
+                        owner: entity,
+                        radius: spell.radius,
+                        damage: spell.damage,
+                    });
+                }
+                Spell::BlastLaunch => {
+                    // Checked above, before anything was spent.
+                    let Some(sprite) = &spell.sprite else {
+                        continue;
+                    };
+
+                    mage.firing_spell = true;
+                    launch_events.send(LaunchBlast {
+                        owner: entity,
+                        origin: position.0 + aim.0 * TILE_SIZE / 2.0,
+                        direction: aim.0,
+                        speed: spell.speed,
+                        lifetime: spell.lifetime,
+                        sprite: sprite.clone(),
+                    });
+                }
+            }
+
+            cooldowns.start(handle.id(), spell.cooldown);
         }
     }
 }
 
-fn report_spells_used(query: Query<&ActionState<Spell>>) {
-    for ability_state in query.iter() {
-        for ability in ability_state.get_just_pressed() {
-            dbg!(ability);
+fn movevement(
+    mut mage_query: Query<(
+        &PlayerInput,
+        &mut Mage,
+        &mut Aim,
+        &AimMode,
+        &mut CharacterController,
+        &Health,
+    )>,
+) {
+    for (input, mut mage, mut aim, aim_mode, mut controller, health) in mage_query.iter_mut() {
+        controller.velocity = Vec2::ZERO;
+        mage.is_walking = false;
+
+        if mage.firing_spell || health.is_dead() {
+            continue;
         }
-    }
-}
 
-fn use_spell(mut commands: Commands, mut mage_query: Query<(&ActionState<Spell>, &mut Mage)>) {
-    let (action_state, mut mage) = mage_query.single_mut();
+        let movement = input.movement;
+
+        mage.is_walking = movement != Vec2::ZERO;
+        controller.velocity = movement * MAGE_SPEED;
 
-    if action_state.just_pressed(Spell::BlastLaunch) {
-        mage.firing_spell = true;
+        if mage.is_walking && *aim_mode == AimMode::Movement {
+            aim.0 = movement.normalize();
+        }
     }
 }
 
-fn movevement(mut mage_query: Query<(&ActionState<MageActions>, &mut Mage, &mut LinearVelocity)>) {
-    let (action_state, mut mage, mut velocity) = mage_query.single_mut();
-
-    velocity.x = 0.0;
-    velocity.y = 0.0;
-
-    if mage.firing_spell {
+/// Once its death has played out, a fallen mage leaves the arena while anyone else is still
+/// standing. The run ends with the last one, who stays put behind the game over screen.
+fn end_run_on_death(
+    mut commands: Commands,
+    mut finished_events: EventReader<AnimationFinished>,
+    mage_query: Query<&Health, With<Mage>>,
+    mut lost_events: EventWriter<RunLost>,
+) {
+    let fallen: Vec<Entity> = finished_events
+        .read()
+        .filter(|finished| finished.clip == "die" && mage_query.contains(finished.entity))
+        .map(|finished| finished.entity)
+        .collect();
+    if fallen.is_empty() {
         return;
     }
-    mage.is_walking = false;
 
-    if action_state.pressed(MageActions::Right) {
-        velocity.x = 10.0;
-        mage.is_walking = true;
-    }
-    if action_state.pressed(MageActions::Left) {
-        velocity.x = -10.0;
-        mage.is_walking = true;
-    }
-    if action_state.pressed(MageActions::Up) {
-        velocity.y = 10.0;
-        mage.is_walking = true;
+    if mage_query.iter().all(Health::is_dead) {
+        lost_events.send(RunLost);
+    } else {
+        for entity in fallen {
+            commands.entity(entity).despawn_recursive();
+        }
     }
-    if action_state.pressed(MageActions::Down) {
-        velocity.y = -10.0;
-        mage.is_walking = true;
+}
+
+fn finish_casting(
+    mut finished_events: EventReader<AnimationFinished>,
+    mut mage_query: Query<&mut Mage>,
+) {
+    for finished in finished_events.read() {
+        if finished.clip != "cast" {
+            continue;
+        }
+
+        if let Ok(mut mage) = mage_query.get_mut(finished.entity) {
+            mage.firing_spell = false;
+        }
     }
 }
 
+/// Which clip plays is up to the transitions `setup_mage` loads the spritesheet with.
 fn animate_mage(
-    time: Res<Time>,
-    mut mage_query: Query<(&mut Mage, &mut RepeatingAnimation, &mut TextureAtlasSprite)>,
+    mut mage_query: Query<(&Mage, &Health, Has<Invulnerable>, &mut AnimationConditions)>,
 ) {
-    let (mut mage, mut walking_animation, mut sprite) = mage_query.single_mut();
+    for (mage, health, invulnerable, mut conditions) in mage_query.iter_mut() {
+        conditions.set("dead", health.is_dead());
+        conditions.set("casting", mage.firing_spell);
+        conditions.set("hurt", invulnerable);
+        conditions.set("walking", mage.is_walking);
+    }
+}
 
-    if mage.firing_spell {
-        if mage.spell_cooldown.elapsed().is_zero() {
-            sprite.index = 2;
+fn toggle_aim_mode(mut mage_query: Query<(&PlayerInput, &mut AimMode)>) {
+    for (input, mut aim_mode) in mage_query.iter_mut() {
+        if input.just_pressed(MageActions::ToggleAimMode) {
+            *aim_mode = match *aim_mode {
+                AimMode::Movement => AimMode::Cursor,
+                AimMode::Cursor | AimMode::Stick => AimMode::Movement,
+            };
         }
+    }
+}
 
-        mage.spell_cooldown.tick(time.delta());
-        if mage.spell_cooldown.just_finished() {
-            mage.firing_spell = false;
-            mage.spell_cooldown.reset();
-            sprite.index = 0;
+fn aim_with_stick(mut mage_query: Query<(&PlayerInput, &mut Aim, &mut AimMode)>) {
+    for (input, mut aim, mut aim_mode) in mage_query.iter_mut() {
+        if input.aim_stick != Vec2::ZERO {
+            *aim_mode = AimMode::Stick;
+            aim.0 = input.aim_stick.normalize();
+        } else if input.cursor_moved && *aim_mode == AimMode::Stick {
+            // Touching the mouse hands aiming back to the cursor.
+            *aim_mode = AimMode::Cursor;
         }
-    } else if mage.is_walking {
-        walking_animation.frame_timer.tick(time.delta());
+    }
+}
+
+fn aim_at_cursor(mut mage_query: Query<(&PlayerInput, &mut Aim, &AimMode, &Position)>) {
+    for (input, mut aim, aim_mode, position) in mage_query.iter_mut() {
+        // Keep the last aim while the cursor is outside the window.
+        let (AimMode::Cursor, Some(cursor)) = (aim_mode, input.cursor) else {
+            continue;
+        };
+
+        let to_cursor = cursor - position.0;
+        if to_cursor != Vec2::ZERO {
+            aim.0 = to_cursor.normalize();
+        }
+    }
+}
+
+/// Input belongs to this machine rather than the simulation, so snapshots leave it out, and a
+/// mage that a rollback brings back after it left the arena comes back without any.
+fn reattach_mage_input(
+    mut commands: Commands,
+    bindings: Res<KeyBindings>,
+    players: Res<LocalPlayers>,
+    seating: Res<Seating>,
+    gamepads: Res<Gamepads>,
+    mage_query: Query<(Entity, &Player), (With<Mage>, Without<PendingInput>)>,
+) {
+    let seats = seating.seats(players.0);
+    for (entity, &player) in mage_query.iter() {
+        commands.entity(entity).insert((
+            seating.seat(player).map_or_else(InputMap::default, |seat| {
+                bindings.input_map(seat, seats, &gamepads)
+            }),
+            ActionState::<MageActions>::default(),
+            PendingInput::default(),
+        ));
+    }
+}
 
-        if walking_animation.frame_timer.just_finished() {
-            sprite.index = walking_animation.next_frame_index.next().unwrap() as usize;
+fn flip_to_aim(mut mage_query: Query<(&Aim, &mut TextureAtlasSprite), With<Mage>>) {
+    for (aim, mut sprite) in mage_query.iter_mut() {
+        // Keep the last horizontal facing when aiming straight up or down.
+        if aim.x != 0.0 {
+            sprite.flip_x = aim.x < 0.0;
         }
     }
 }
 
 impl Plugin for MagePlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup_mage)
+        app.init_resource::<LocalPlayers>()
+            .add_systems(StartRun, setup_mage)
             .add_plugins(InputManagerPlugin::<MageActions>::default())
-            .add_plugins(InputManagerPlugin::<Spell>::default())
+            .rollback_component::<Mage>()
+            .rollback_component::<Player>()
+            .rollback_component::<Aim>()
+            .rollback_component::<AimMode>()
+            .rollback_component::<SpellSlotMap>()
+            .configure_sets(
+                FixedUpdate,
+                MageSet.in_set(TickSet::Act).before(SpriteAnimationSet),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    (
+                        toggle_aim_mode,
+                        aim_with_stick,
+                        aim_at_cursor,
+                        use_spell,
+                        movevement,
+                        animate_mage,
+                    )
+                        .chain()
+                        .in_set(MageSet),
+                    // Casting ends on the tick its animation does.
+                    finish_casting.after(SpriteAnimationSet),
+                )
+                    .in_set(TickSet::Act)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                FixedUpdate,
+                end_run_on_death
+                    .in_set(TickSet::Cleanup)
+                    .run_if(in_state(GameState::Playing)),
+            )
             .add_systems(
                 PreUpdate,
-                copy_action_state.after(InputManagerSystem::ManualControl),
+                reattach_mage_input.before(InputManagerSystem::Update),
             )
-            .add_systems(Update, report_spells_used)
-            .add_systems(Update, (use_spell, movevement, animate_mage).chain());
+            .add_systems(Update, flip_to_aim.run_if(in_state(GameState::Playing)));
     }
 }
@@ -1,40 +1,136 @@
-mod mage;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
 
-use bevy::{
-    prelude::*,
-    window::{self, WindowResolution},
-};
+use bevy::{prelude::*, window::WindowResolution};
 use bevy_xpbd_2d::plugins::{PhysicsDebugPlugin, PhysicsPlugins};
-use mage::MagePlugin;
-use prelude::{WINDOW_HEIGHT, WINDOW_WIDTH};
+use magic_mania::mage::Player;
+use magic_mania::netcode::{OnlineSession, SimulatedNetwork, UdpChannel};
+use magic_mania::prelude::{WINDOW_HEIGHT, WINDOW_WIDTH};
+use magic_mania::replay::{Recording, ReplayPlugin};
+use magic_mania::settings::SettingsDir;
+use magic_mania::GamePlugins;
 
-pub mod prelude {
-    pub const TILE_SIZE: f32 = 32.0;
-    pub const WINDOW_HEIGHT: f32 = TILE_SIZE * 15.0;
-    pub const WINDOW_WIDTH: f32 = TILE_SIZE * 15.0;
+/// The recording passed with `--replay <file>`, if any.
+fn replay_from_args() -> Option<Recording> {
+    let path = std::env::args()
+        .skip_while(|arg| arg != "--replay")
+        .nth(1)
+        .map(PathBuf::from)?;
+
+    match Recording::load(&path) {
+        Ok(recording) => Some(recording),
+        Err(error) => {
+            eprintln!("Couldn't load replay {}: {error}", path.display());
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Where bindings and the profile are kept: the directory passed with `--settings <dir>`, or
+/// the one the game is run from.
+fn settings_from_args() -> SettingsDir {
+    let dir = std::env::args()
+        .skip_while(|arg| arg != "--settings")
+        .nth(1)
+        .unwrap_or_else(|| ".".to_string());
+    SettingsDir(PathBuf::from(dir))
+}
+
+const ONLINE_USAGE: &str =
+    "--online <player> <port> <other players' addresses>... [--latency <ms>] [--loss <fraction>]";
+
+/// The session to join when passed `--online`, with each player's machine running one with its
+/// own player number, e.g. `--online 0 7000 127.0.0.1:7001` and `--online 1 7001 127.0.0.1:7000`.
+/// `--latency` and `--loss` make the connection worse on purpose, for testing.
+fn online_from_args() -> Option<OnlineSession> {
+    let args: Vec<String> = std::env::args().collect();
+    if !args.iter().any(|arg| arg == "--online") {
+        return None;
+    }
+
+    let online: Vec<&str> = args
+        .iter()
+        .skip_while(|arg| *arg != "--online")
+        .skip(1)
+        .take_while(|arg| !arg.starts_with("--"))
+        .map(String::as_str)
+        .collect();
+    let option = |name: &str| {
+        args.iter()
+            .skip_while(|arg| *arg != name)
+            .nth(1)
+            .map(String::as_str)
+    };
+
+    match start_online(&online, option("--latency"), option("--loss")) {
+        Ok(session) => Some(session),
+        Err(error) => {
+            eprintln!("Couldn't go online: {error}\nUsage: {ONLINE_USAGE}");
+            std::process::exit(1);
+        }
+    }
 }
 
-fn camera_setup(mut commands: Commands) {
-    commands.spawn(Camera2dBundle::default());
+fn start_online(
+    online: &[&str],
+    latency: Option<&str>,
+    loss: Option<&str>,
+) -> Result<OnlineSession, Box<dyn Error>> {
+    let [player, port, remotes @ ..] = online else {
+        return Err("missing the player number and port".into());
+    };
+
+    let player = Player(player.parse()?);
+    let port: u16 = port.parse()?;
+    let remotes = remotes
+        .iter()
+        .map(|address| address.parse())
+        .collect::<Result<Vec<SocketAddr>, _>>()?;
+    let latency = Duration::from_millis(latency.map_or(Ok(0), str::parse)?);
+    let loss = loss.map_or(Ok(0.0), str::parse)?;
+
+    let socket = UdpChannel::bind(SocketAddr::from(([0, 0, 0, 0], port)))?;
+    let network = SimulatedNetwork::new(socket)
+        .with_latency(latency, latency / 4)
+        .with_loss(loss);
+    Ok(OnlineSession::start(player, &remotes, network)?)
 }
 
 fn main() {
-    App::new()
-        .add_plugins(
-            DefaultPlugins
-                .set(ImagePlugin::default_nearest())
-                .set(WindowPlugin {
-                    primary_window: Some(Window {
-                        title: "Magic Mania".to_string(),
-                        resolution: WindowResolution::new(WINDOW_WIDTH, WINDOW_HEIGHT),
-                        ..default()
-                    }),
+    let replay = replay_from_args();
+    let online = online_from_args();
+    // A replay plays out the way it was recorded, whatever has been changed since.
+    let settings = replay.is_none().then(settings_from_args);
+
+    let mut app = App::new();
+    app.add_plugins(
+        DefaultPlugins
+            .set(ImagePlugin::default_nearest())
+            .set(WindowPlugin {
+                primary_window: Some(Window {
+                    title: "Magic Mania".to_string(),
+                    resolution: WindowResolution::new(WINDOW_WIDTH, WINDOW_HEIGHT),
                     ..default()
                 }),
-        )
-        .add_systems(Update, window::close_on_esc)
-        .add_systems(Startup, camera_setup)
-        .add_plugins((PhysicsPlugins::default(), PhysicsDebugPlugin::default()))
-        .add_plugins(MagePlugin)
-        .run();
+                ..default()
+            }),
+    )
+    .add_plugins((
+        PhysicsPlugins::new(FixedUpdate),
+        PhysicsDebugPlugin::default(),
+    ))
+    .add_plugins(GamePlugins.set(ReplayPlugin {
+        replay,
+        ..default()
+    }));
+
+    if let Some(settings) = settings {
+        app.insert_resource(settings);
+    }
+    if let Some(online) = online {
+        app.insert_resource(online);
+    }
+    app.run();
 }
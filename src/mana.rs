@@ -0,0 +1,127 @@
+use bevy::prelude::*;
+use bevy_xpbd_2d::prelude::*;
+
+use crate::blast::BlastSet;
+use crate::game_state::{GameState, InGame};
+use crate::layers::Layer;
+use crate::mage::MageSet;
+use crate::rollback::RollbackApp;
+use crate::simulation::TickSet;
+
+pub struct ManaPlugin;
+
+const MANA_PICKUP_SIZE: f32 = 8.0;
+
+#[derive(Component, Clone)]
+pub struct Mana {
+    pub current: f32,
+    pub max: f32,
+    /// Mana regained per second.
+    pub regeneration: f32,
+}
+
+impl Mana {
+    pub fn new(max: f32, regeneration: f32) -> Self {
+        Self {
+            current: max,
+            max,
+            regeneration,
+        }
+    }
+
+    /// Deducts `cost` if there is enough mana, returning whether it was spent.
+    pub fn try_spend(&mut self, cost: f32) -> bool {
+        if self.current < cost {
+            return false;
+        }
+
+        self.current -= cost;
+        true
+    }
+
+    pub fn restore(&mut self, amount: f32) {
+        self.current = (self.current + amount).min(self.max);
+    }
+}
+
+#[derive(Component, Clone)]
+pub struct ManaPickup {
+    pub amount: f32,
+}
+
+#[derive(Bundle)]
+pub struct ManaPickupBundle {
+    pickup: ManaPickup,
+    sprite: SpriteBundle,
+    rigid_body: RigidBody,
+    collider: Collider,
+    sensor: Sensor,
+    layers: CollisionLayers,
+    in_game: InGame,
+}
+
+impl ManaPickupBundle {
+    pub fn new(position: Vec2, amount: f32) -> Self {
+        Self {
+            pickup: ManaPickup { amount },
+            sprite: SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgb(0.3, 0.5, 1.0),
+                    custom_size: Some(Vec2::splat(MANA_PICKUP_SIZE)),
+                    ..default()
+                },
+                transform: Transform::from_translation(position.extend(0.25)),
+                ..default()
+            },
+            rigid_body: RigidBody::Static,
+            collider: Collider::ball(MANA_PICKUP_SIZE / 2.0),
+            sensor: Sensor,
+            layers: CollisionLayers::new([Layer::Pickup], [Layer::Player]),
+            in_game: InGame,
+        }
+    }
+}
+
+fn regenerate_mana(time: Res<Time>, mut mana_query: Query<&mut Mana>) {
+    for mut mana in mana_query.iter_mut() {
+        let regenerated = mana.regeneration * time.delta_seconds();
+        mana.restore(regenerated);
+    }
+}
+
+fn collect_mana_pickups(
+    mut commands: Commands,
+    mut collision_events: EventReader<CollisionStarted>,
+    pickup_query: Query<&ManaPickup>,
+    mut mana_query: Query<&mut Mana>,
+) {
+    for CollisionStarted(first, second) in collision_events.read() {
+        for (pickup_entity, collector) in [(*first, *second), (*second, *first)] {
+            let (Ok(pickup), Ok(mut mana)) = (
+                pickup_query.get(pickup_entity),
+                mana_query.get_mut(collector),
+            ) else {
+                continue;
+            };
+
+            mana.restore(pickup.amount);
+            commands.entity(pickup_entity).despawn();
+        }
+    }
+}
+
+impl Plugin for ManaPlugin {
+    fn build(&self, app: &mut App) {
+        app.rollback_component::<Mana>()
+            .rollback_component::<ManaPickup>()
+            .add_systems(
+                FixedUpdate,
+                (
+                    regenerate_mana.in_set(TickSet::Act).before(MageSet),
+                    // Like every other response to collisions, after the blasts.
+                    collect_mana_pickups.in_set(TickSet::React).after(BlastSet),
+                )
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
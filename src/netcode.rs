@@ -0,0 +1,515 @@
+use std::collections::BTreeSet;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use bevy::app::RunFixedUpdateLoop;
+use bevy::prelude::*;
+use ggrs::{
+    Config, GgrsError, GgrsEvent, GgrsRequest, InputStatus, Message, NonBlockingSocket, P2PSession,
+    PlayerType, SessionBuilder, SessionState,
+};
+use leafwing_input_manager::Actionlike;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::blast::BlastExploded;
+use crate::game_state::{GameState, RunLost};
+use crate::health::Damaged;
+use crate::level::LevelBounds;
+use crate::mage::{LocalPlayers, MageActions, Player, SpellSlotMap};
+use crate::player_input::{PendingInput, PlayerInput, Seating, TickInput};
+use crate::rollback::Snapshot;
+use crate::simulation::{RunSeed, TickSet};
+
+/// Plays a run together with other machines once an `OnlineSession` is inserted.
+///
+/// Every machine simulates every player. Input from other machines arrives late, so until it
+/// does their players are assumed to carry on doing what they last did. When a guess turns out
+/// wrong, the game goes back to a snapshot from before it and simulates forward again with the
+/// real input.
+pub struct NetcodePlugin;
+
+/// Ticks that local input is held back, which hides that much latency without rolling back.
+const INPUT_DELAY: usize = 2;
+/// Comfortably bigger than any message ggrs sends.
+const MAX_MESSAGE_SIZE: usize = 4096;
+/// Every machine has to start from the same seed, so online runs use this unless `RunSeed`
+/// says otherwise.
+const ONLINE_SEED: u64 = 0x0a11_ed_5eed;
+
+#[derive(Debug)]
+struct NetConfig;
+
+impl Config for NetConfig {
+    type Input = NetInput;
+    type State = Snapshot;
+    type Address = SocketAddr;
+}
+
+/// A `TickInput` packed small enough to send every tick. Sticks lose a little precision, and
+/// the cursor is rounded to whole pixels.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug, Serialize, Deserialize)]
+pub struct NetInput {
+    movement: [i8; 2],
+    aim_stick: [i8; 2],
+    cursor: Option<[i16; 2]>,
+    cursor_moved: bool,
+    /// One bit per `MageActions` variant, in the order they're declared.
+    pressed: u16,
+    just_pressed: u16,
+}
+
+fn pack_axis(axis: Vec2) -> [i8; 2] {
+    let axis = (axis.clamp(Vec2::NEG_ONE, Vec2::ONE) * i8::MAX as f32).round();
+    [axis.x as i8, axis.y as i8]
+}
+
+fn unpack_axis(axis: [i8; 2]) -> Vec2 {
+    Vec2::new(axis[0] as f32, axis[1] as f32) / i8::MAX as f32
+}
+
+fn pack_actions(actions: &BTreeSet<MageActions>) -> u16 {
+    MageActions::variants()
+        .enumerate()
+        .filter(|(_, action)| actions.contains(action))
+        .fold(0, |bits, (index, _)| bits | 1 << index)
+}
+
+fn unpack_actions(bits: u16) -> BTreeSet<MageActions> {
+    MageActions::variants()
+        .enumerate()
+        .filter(|(index, _)| bits & 1 << index != 0)
+        .map(|(_, action)| action)
+        .collect()
+}
+
+impl From<&TickInput> for NetInput {
+    fn from(input: &TickInput) -> Self {
+        Self {
+            movement: pack_axis(input.movement),
+            aim_stick: pack_axis(input.aim_stick),
+            cursor: input
+                .cursor
+                .map(|cursor| [cursor.x.round() as i16, cursor.y.round() as i16]),
+            cursor_moved: input.cursor_moved,
+            pressed: pack_actions(&input.pressed),
+            just_pressed: pack_actions(&input.just_pressed),
+        }
+    }
+}
+
+impl From<NetInput> for TickInput {
+    fn from(input: NetInput) -> Self {
+        Self {
+            movement: unpack_axis(input.movement),
+            aim_stick: unpack_axis(input.aim_stick),
+            cursor: input
+                .cursor
+                .map(|cursor| Vec2::new(cursor[0] as f32, cursor[1] as f32)),
+            cursor_moved: input.cursor_moved,
+            pressed: unpack_actions(input.pressed),
+            just_pressed: unpack_actions(input.just_pressed),
+        }
+    }
+}
+
+/// Sends each message as a UDP datagram.
+pub struct UdpChannel {
+    socket: UdpSocket,
+}
+
+impl UdpChannel {
+    pub fn bind(address: SocketAddr) -> io::Result<Self> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self { socket })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+}
+
+impl NonBlockingSocket<SocketAddr> for UdpChannel {
+    fn send_to(&mut self, message: &Message, address: &SocketAddr) {
+        match bincode::serialize(message) {
+            // Anything that doesn't arrive gets sent again, so a failed send is no different.
+            Ok(bytes) => {
+                let _ = self.socket.send_to(&bytes, address);
+            }
+            Err(error) => warn!("Couldn't encode a message for {address}: {error}"),
+        }
+    }
+
+    fn receive_all_messages(&mut self) -> Vec<(SocketAddr, Message)> {
+        let mut buffer = [0; MAX_MESSAGE_SIZE];
+        let mut messages = Vec::new();
+
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((length, from)) => match bincode::deserialize(&buffer[..length]) {
+                    Ok(message) => messages.push((from, message)),
+                    Err(error) => warn!("Ignoring a garbled message from {from}: {error}"),
+                },
+                // Some platforms report an earlier send to a peer that wasn't listening yet.
+                Err(error) if error.kind() == io::ErrorKind::ConnectionReset => continue,
+                Err(error) => {
+                    if error.kind() != io::ErrorKind::WouldBlock {
+                        warn!("Couldn't receive messages: {error}");
+                    }
+                    return messages;
+                }
+            }
+        }
+    }
+}
+
+/// Wraps a socket to send messages late, out of order or not at all, like a bad connection.
+pub struct SimulatedNetwork<S> {
+    socket: S,
+    latency: Duration,
+    jitter: Duration,
+    loss: f64,
+    rng: StdRng,
+    in_flight: Vec<(Instant, SocketAddr, Message)>,
+}
+
+impl<S: NonBlockingSocket<SocketAddr>> SimulatedNetwork<S> {
+    pub fn new(socket: S) -> Self {
+        Self {
+            socket,
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            loss: 0.0,
+            rng: StdRng::seed_from_u64(0),
+            in_flight: Vec::new(),
+        }
+    }
+
+    /// Delays every message by `latency`, plus anything up to `jitter` more.
+    pub fn with_latency(mut self, latency: Duration, jitter: Duration) -> Self {
+        self.latency = latency;
+        self.jitter = jitter;
+        self
+    }
+
+    /// Drops this fraction of messages, from 0.0 to 1.0.
+    pub fn with_loss(mut self, loss: f64) -> Self {
+        self.loss = loss.clamp(0.0, 1.0);
+        self
+    }
+
+    fn send_arrived(&mut self) {
+        let now = Instant::now();
+        let mut index = 0;
+        while index < self.in_flight.len() {
+            if self.in_flight[index].0 <= now {
+                let (_, address, message) = self.in_flight.swap_remove(index);
+                self.socket.send_to(&message, &address);
+            } else {
+                index += 1;
+            }
+        }
+    }
+}
+
+impl<S: NonBlockingSocket<SocketAddr>> NonBlockingSocket<SocketAddr> for SimulatedNetwork<S> {
+    fn send_to(&mut self, message: &Message, address: &SocketAddr) {
+        if !self.rng.gen_bool(self.loss) {
+            let delay = self.latency + self.jitter.mul_f64(self.rng.gen());
+            self.in_flight
+                .push((Instant::now() + delay, *address, message.clone()));
+        }
+        self.send_arrived();
+    }
+
+    fn receive_all_messages(&mut self) -> Vec<(SocketAddr, Message)> {
+        self.send_arrived();
+        self.socket.receive_all_messages()
+    }
+}
+
+/// A run shared with players on other machines. Insert it before the app first updates to play
+/// online.
+///
+/// Runs start once everyone has connected and pressed play. Only the first run of a session is
+/// kept in sync, so there's no trying again after a game over, and pausing holds everyone else
+/// up.
+#[derive(Resource)]
+pub struct OnlineSession {
+    session: P2PSession<NetConfig>,
+    local_player: Player,
+    /// Ticks to sit out, so that a machine running ahead lets the others catch up.
+    skip_ticks: u32,
+    /// The tick that lost the run, which only ends it once everyone's input up to there has
+    /// arrived. Until then a rollback can still take it back.
+    lost_at: Option<i32>,
+}
+
+impl OnlineSession {
+    /// Starts connecting to the other players' machines, given in player order without
+    /// `local_player`.
+    pub fn start(
+        local_player: Player,
+        remotes: &[SocketAddr],
+        socket: impl NonBlockingSocket<SocketAddr> + 'static,
+    ) -> Result<Self, GgrsError> {
+        let players = remotes.len() + 1;
+        let mut remotes = remotes.iter().copied();
+
+        let mut builder = SessionBuilder::<NetConfig>::new()
+            .with_num_players(players)
+            .with_input_delay(INPUT_DELAY);
+        for index in 0..players {
+            let player_type = if index == local_player.0 {
+                PlayerType::Local
+            } else {
+                PlayerType::Remote(remotes.next().ok_or_else(|| GgrsError::InvalidRequest {
+                    info: format!("{local_player:?} isn't one of the {players} players"),
+                })?)
+            };
+            builder = builder.add_player(player_type, index)?;
+        }
+
+        Ok(Self {
+            session: builder.start_p2p_session(socket)?,
+            local_player,
+            skip_ticks: 0,
+            lost_at: None,
+        })
+    }
+
+    pub fn players(&self) -> usize {
+        self.session.num_players()
+    }
+
+    pub fn local_player(&self) -> Player {
+        self.local_player
+    }
+
+    /// Whether every machine has connected.
+    pub fn is_running(&self) -> bool {
+        self.session.current_state() == SessionState::Running
+    }
+
+    /// The last tick simulated here, which may still be partly guesswork.
+    pub fn current_tick(&self) -> i32 {
+        self.session.current_frame()
+    }
+}
+
+/// The gameplay tick, taken out of `FixedUpdate` so that it runs when the session asks for it.
+#[derive(Resource)]
+struct TickSchedule(Schedule);
+
+/// Every player's input for the tick being simulated, indexed by `Player`.
+#[derive(Resource)]
+struct OnlineInputs(Vec<TickInput>);
+
+fn take_over_ticks(world: &mut World) {
+    let session = world.resource::<OnlineSession>();
+    let (players, local) = (session.players(), session.local_player());
+
+    world.insert_resource(LocalPlayers(players));
+    world.insert_resource(Seating::Online { local });
+    if !world.contains_resource::<RunSeed>() {
+        world.insert_resource(RunSeed(ONLINE_SEED));
+    }
+
+    let schedule = world
+        .resource_mut::<Schedules>()
+        .remove(FixedUpdate)
+        .expect("the gameplay tick runs in FixedUpdate");
+    world.insert_resource(TickSchedule(schedule));
+}
+
+/// Every machine has to start from the same state, so nothing is simulated until the level and
+/// everyone's spells have loaded.
+fn loaded(world: &mut World) -> bool {
+    if !world.contains_resource::<LevelBounds>() {
+        return false;
+    }
+
+    let asset_server = world.resource::<AssetServer>().clone();
+    let mut slot_map_query = world.query::<&SpellSlotMap>();
+    let mut slot_maps = slot_map_query.iter(world).peekable();
+
+    slot_maps.peek().is_some()
+        && slot_maps.all(|slot_map| {
+            slot_map
+                .values()
+                .all(|spell| asset_server.is_loaded_with_dependencies(spell.id()))
+        })
+}
+
+fn local_input(world: &mut World, local: Player) -> NetInput {
+    world
+        .query::<(&Player, &PendingInput)>()
+        .iter(world)
+        .find(|(player, _)| **player == local)
+        .map_or_else(NetInput::default, |(_, pending)| (&**pending).into())
+}
+
+fn take_events<E: Event>(world: &mut World) -> Vec<E> {
+    world.resource_mut::<Events<E>>().drain().collect()
+}
+
+/// Replaces whatever was sent since `take_events` with what it took.
+fn put_back_events<E: Event>(world: &mut World, events: Vec<E>) {
+    let mut queue = world.resource_mut::<Events<E>>();
+    queue.clear();
+    queue.extend(events);
+}
+
+fn handle_requests(
+    world: &mut World,
+    online: &mut OnlineSession,
+    requests: Vec<GgrsRequest<NetConfig>>,
+) {
+    // Every tick is saved before it's simulated, so saves and loads say which tick is next.
+    let mut tick = online.current_tick();
+    // Only the last tick is new. The ones before it are being simulated again after a rollback.
+    let mut replays = requests
+        .iter()
+        .filter(|request| matches!(request, GgrsRequest::AdvanceFrame { .. }))
+        .count()
+        .saturating_sub(1);
+    for request in requests {
+        match request {
+            GgrsRequest::SaveGameState { cell, frame } => {
+                cell.save(frame, Some(Snapshot::take(world)), None);
+                tick = frame;
+            }
+            GgrsRequest::LoadGameState { cell, frame } => {
+                let snapshot = cell.load().expect("only saved ticks are rolled back to");
+                snapshot.restore(world);
+
+                tick = frame;
+                if online.lost_at.is_some_and(|lost_at| lost_at >= frame) {
+                    online.lost_at = None;
+                }
+            }
+            GgrsRequest::AdvanceFrame { inputs } => {
+                let inputs = inputs
+                    .into_iter()
+                    .map(|(input, status)| match status {
+                        InputStatus::Disconnected => TickInput::default(),
+                        InputStatus::Confirmed | InputStatus::Predicted => input.into(),
+                    })
+                    .collect();
+                world.insert_resource(OnlineInputs(inputs));
+
+                // What these ticks showed the player, they already showed the first time round.
+                let replaying = replays > 0;
+                replays = replays.saturating_sub(1);
+                let shown = replaying.then(|| {
+                    (
+                        take_events::<Damaged>(world),
+                        take_events::<BlastExploded>(world),
+                    )
+                });
+
+                world.resource_scope(|world, mut schedule: Mut<TickSchedule>| {
+                    *world.resource_mut::<Time>() = world.resource::<Time<Fixed>>().as_generic();
+                    schedule.0.run(world);
+                });
+
+                if let Some((damaged, exploded)) = shown {
+                    put_back_events(world, damaged);
+                    put_back_events(world, exploded);
+                }
+
+                if world.resource_mut::<Events<RunLost>>().drain().count() > 0 {
+                    online.lost_at.get_or_insert(tick);
+                }
+                tick += 1;
+            }
+        }
+    }
+}
+
+/// Takes the place of the fixed timestep: each tick that's due is handed to the session, which
+/// decides what to simulate.
+fn run_online_ticks(world: &mut World) {
+    world.resource_scope(|world, mut online: Mut<OnlineSession>| {
+        online.session.poll_remote_clients();
+        let events: Vec<_> = online.session.events().collect();
+        for event in events {
+            match event {
+                GgrsEvent::WaitRecommendation { skip_frames } => online.skip_ticks += skip_frames,
+                event => info!("Online: {event:?}"),
+            }
+        }
+
+        let ready = online.is_running()
+            && *world.resource::<State<GameState>>().get() == GameState::Playing
+            && loaded(world);
+
+        while world.resource_mut::<Time<Fixed>>().expend() {
+            if !ready {
+                continue;
+            }
+            if online.skip_ticks > 0 {
+                online.skip_ticks -= 1;
+                continue;
+            }
+
+            let local = online.local_player;
+            let input = local_input(world, local);
+            if let Err(error) = online.session.add_local_input(local.0, input) {
+                warn!("Couldn't send input: {error}");
+                continue;
+            }
+
+            match online.session.advance_frame() {
+                Ok(requests) => handle_requests(world, &mut online, requests),
+                // Too far ahead of everyone else to keep guessing, so wait for them.
+                Err(GgrsError::PredictionThreshold) => {}
+                Err(error) => warn!("Couldn't advance the online session: {error}"),
+            }
+        }
+
+        *world.resource_mut::<Time>() = world.resource::<Time<Virtual>>().as_generic();
+
+        if online
+            .lost_at
+            .is_some_and(|lost_at| lost_at <= online.session.confirmed_frame())
+        {
+            online.lost_at = None;
+            world
+                .resource_mut::<NextState<GameState>>()
+                .set(GameState::GameOver);
+        }
+    });
+}
+
+fn apply_online_inputs(
+    inputs: Res<OnlineInputs>,
+    mut player_query: Query<(&Player, &mut PlayerInput)>,
+) {
+    for (player, mut input) in player_query.iter_mut() {
+        input.set(inputs.0.get(player.0).cloned().unwrap_or_default());
+    }
+}
+
+impl Plugin for NetcodePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Startup,
+            take_over_ticks.run_if(resource_exists::<OnlineSession>()),
+        )
+        .add_systems(
+            RunFixedUpdateLoop,
+            run_online_ticks.run_if(resource_exists::<OnlineSession>()),
+        )
+        .add_systems(
+            FixedUpdate,
+            apply_online_inputs
+                .after(TickSet::Input)
+                .before(TickSet::Act)
+                .run_if(resource_exists::<OnlineInputs>()),
+        );
+    }
+}
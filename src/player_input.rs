@@ -0,0 +1,161 @@
+use std::collections::BTreeSet;
+
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use leafwing_input_manager::plugin::InputManagerSystem;
+use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game_state::GameState;
+use crate::mage::{MageActions, Player};
+use crate::rollback::RollbackApp;
+use crate::simulation::TickSet;
+
+pub struct PlayerInputPlugin;
+
+/// Everything a player did with their controls for one gameplay tick.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TickInput {
+    /// Sticks give a proportional length, keys and the dpad are always at full tilt.
+    pub movement: Vec2,
+    pub aim_stick: Vec2,
+    /// Where the mouse cursor points in the world, or `None` while it's outside the window.
+    pub cursor: Option<Vec2>,
+    pub cursor_moved: bool,
+    pub pressed: BTreeSet<MageActions>,
+    /// Pressed at some point since the previous tick, even if already released again.
+    pub just_pressed: BTreeSet<MageActions>,
+}
+
+impl TickInput {
+    pub fn pressed(&self, action: MageActions) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: MageActions) -> bool {
+        self.just_pressed.contains(&action)
+    }
+}
+
+/// The input gameplay reads for a player, which only changes once per tick.
+///
+/// `ActionState` changes every frame, and a frame can run several ticks or none at all, so
+/// reading it directly would see presses twice or miss them. Frames are gathered in
+/// `PendingInput` and handed over as a whole at the start of the next tick.
+#[derive(Component, Clone, Default, Deref)]
+pub struct PlayerInput(TickInput);
+
+impl PlayerInput {
+    /// Replaces this tick's input with one from somewhere other than the local controls.
+    pub fn set(&mut self, input: TickInput) {
+        self.0 = input;
+    }
+}
+
+/// What the local controls have done towards the next tick so far.
+///
+/// It belongs to this machine rather than the simulation, so unlike `PlayerInput` it's left
+/// alone when the game rolls back.
+#[derive(Component, Default, Deref)]
+pub struct PendingInput(TickInput);
+
+/// Which players are at this machine. On a shared screen that's all of them, each taking the
+/// controls in turn: the first gets the mouse and keyboard, the second the rest of the keyboard.
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Seating {
+    #[default]
+    SharedScreen,
+    /// Only `local` is here. Everyone else's input arrives over the network.
+    Online { local: Player },
+}
+
+impl Seating {
+    /// Which turn at this machine's controls `player` has, or `None` if they're elsewhere.
+    pub fn seat(self, player: Player) -> Option<usize> {
+        match self {
+            Seating::SharedScreen => Some(player.0),
+            Seating::Online { local } => (player == local).then_some(0),
+        }
+    }
+
+    /// How many of `players` are sharing this machine's controls.
+    pub fn seats(self, players: usize) -> usize {
+        match self {
+            Seating::SharedScreen => players,
+            Seating::Online { .. } => 1,
+        }
+    }
+
+    pub fn is_online(self) -> bool {
+        matches!(self, Seating::Online { .. })
+    }
+}
+
+/// Run condition for anything that changes gameplay from outside the tick, which would put
+/// machines playing online out of sync.
+pub fn offline(seating: Res<Seating>) -> bool {
+    !seating.is_online()
+}
+
+fn gather_input(
+    seating: Res<Seating>,
+    mut cursor_moved_events: EventReader<CursorMoved>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<Camera2d>>,
+    mut player_query: Query<(&ActionState<MageActions>, &mut PendingInput, &Player)>,
+) {
+    let cursor_moved = cursor_moved_events.read().count() > 0;
+    let cursor = match (window_query.get_single(), camera_query.get_single()) {
+        (Ok(window), Ok((camera, camera_transform))) => window
+            .cursor_position()
+            .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor)),
+        _ => None,
+    };
+
+    for (action_state, mut pending, player) in player_query.iter_mut() {
+        // There's only one mouse, and it belongs to the first player here.
+        let has_mouse = seating.seat(*player) == Some(0);
+        let pending = &mut pending.0;
+
+        pending.movement = action_state
+            .axis_pair(MageActions::Move)
+            .map_or(Vec2::ZERO, |axis| axis.xy())
+            .clamp_length_max(1.0);
+        pending.aim_stick = action_state
+            .axis_pair(MageActions::Aim)
+            .map_or(Vec2::ZERO, |axis| axis.xy());
+        pending.cursor = cursor.filter(|_| has_mouse);
+        pending.cursor_moved |= cursor_moved && has_mouse;
+        pending.pressed = action_state.get_pressed().into_iter().collect();
+        pending.just_pressed.extend(action_state.get_just_pressed());
+    }
+}
+
+fn sample_input(mut player_query: Query<(&mut PlayerInput, &mut PendingInput)>) {
+    for (mut input, mut pending) in player_query.iter_mut() {
+        input.0 = pending.0.clone();
+
+        // Held controls carry on into the next tick, one-off events don't.
+        pending.0.just_pressed.clear();
+        pending.0.cursor_moved = false;
+    }
+}
+
+impl Plugin for PlayerInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Seating>()
+            .rollback_component::<PlayerInput>()
+            .add_systems(
+                PreUpdate,
+                gather_input
+                    .after(InputManagerSystem::ManualControl)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                FixedUpdate,
+                sample_input
+                    .in_set(TickSet::Input)
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
@@ -0,0 +1,357 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use leafwing_input_manager::axislike::DualAxisData;
+use leafwing_input_manager::plugin::InputManagerSystem;
+use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::game_state::{GameState, StartRun};
+use crate::mage::{LocalPlayers, Mage, MageActions, Player, SpellSlotMap};
+use crate::player_input::{offline, PlayerInput, TickInput};
+use crate::simulation::{GameRng, RunSeed, TickSet};
+
+/// Records every run, saving it to `record_to`, or plays `replay` back instead of reading the
+/// controls.
+pub struct ReplayPlugin {
+    pub replay: Option<Recording>,
+    pub record_to: Option<PathBuf>,
+}
+
+impl Default for ReplayPlugin {
+    fn default() -> Self {
+        Self {
+            replay: None,
+            record_to: Some(PathBuf::from(RECORDING_PATH)),
+        }
+    }
+}
+
+const RECORDING_PATH: &str = "last_run.replay";
+/// Bumped whenever a change to `Recording` or `TickInput` would misread older files.
+const FORMAT_VERSION: u32 = 2;
+
+/// Which spell is in each slot, by asset path.
+pub type Loadout = BTreeMap<MageActions, String>;
+
+/// Everything needed to play a run back exactly: the seed it started from, what every player's
+/// controls did on each tick and which spells were in which slots.
+///
+/// Per-player data is indexed by `Player`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Recording {
+    version: u32,
+    seed: u64,
+    players: u32,
+    ticks: u32,
+    /// Runs of identical ticks as `(count, inputs)`, since input is mostly held for a while.
+    inputs: Vec<(u32, Vec<TickInput>)>,
+    /// Each set of loadouts the players had, from the tick it changed on.
+    loadouts: Vec<(u32, Vec<Loadout>)>,
+}
+
+#[derive(Error, Debug)]
+pub enum RecordingError {
+    #[error("Could not access recording: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not encode recording: {0}")]
+    Encoding(#[from] bincode::Error),
+    #[error("Recording is format version {found}, but this build plays version {expected}")]
+    Version { found: u32, expected: u32 },
+}
+
+impl Recording {
+    pub fn new(seed: u64, players: usize) -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            seed,
+            players: players as u32,
+            ticks: 0,
+            inputs: Vec::new(),
+            loadouts: Vec::new(),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn players(&self) -> usize {
+        self.players as usize
+    }
+
+    /// How many ticks were recorded.
+    pub fn len(&self) -> u32 {
+        self.ticks
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ticks == 0
+    }
+
+    /// Adds a tick, with an input and loadout for each player.
+    pub fn push(&mut self, inputs: &[TickInput], loadouts: &[Loadout]) {
+        match self.inputs.last_mut() {
+            Some((count, last)) if last == inputs => *count += 1,
+            _ => self.inputs.push((1, inputs.to_vec())),
+        }
+
+        if self.loadouts.last().map(|(_, last)| last.as_slice()) != Some(loadouts) {
+            self.loadouts.push((self.ticks, loadouts.to_vec()));
+        }
+        self.ticks += 1;
+    }
+
+    pub fn load(path: &Path) -> Result<Self, RecordingError> {
+        let bytes = fs::read(path)?;
+
+        // The version comes first, so it can be checked before trying to read the rest.
+        let found: u32 = bincode::deserialize(&bytes)?;
+        if found != FORMAT_VERSION {
+            return Err(RecordingError::Version {
+                found,
+                expected: FORMAT_VERSION,
+            });
+        }
+
+        Ok(bincode::deserialize(&bytes)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), RecordingError> {
+        fs::write(path, bincode::serialize(self)?)?;
+        Ok(())
+    }
+}
+
+/// The run being recorded. It starts on the run's first tick, once the seed is known.
+#[derive(Resource, Default)]
+pub struct Recorder(Option<Recording>);
+
+impl Recorder {
+    pub fn recording(&self) -> Option<&Recording> {
+        self.0.as_ref()
+    }
+}
+
+#[derive(Resource)]
+struct RecordTo(PathBuf);
+
+/// A recording being played back, one tick at a time from the start of the run.
+#[derive(Resource)]
+struct Replay {
+    inputs: Vec<Vec<TickInput>>,
+    loadouts: Vec<(u32, Vec<Loadout>)>,
+    tick: u32,
+}
+
+impl Replay {
+    fn new(recording: &Recording) -> Self {
+        let inputs = recording
+            .inputs
+            .iter()
+            .flat_map(|(count, input)| std::iter::repeat(input).take(*count as usize))
+            .cloned()
+            .collect();
+
+        Self {
+            inputs,
+            loadouts: recording.loadouts.clone(),
+            tick: 0,
+        }
+    }
+
+    /// Every player's input for the tick that's up next, if the recording goes that far.
+    fn next_inputs(&self) -> Option<&[TickInput]> {
+        self.inputs.get(self.tick as usize).map(Vec::as_slice)
+    }
+
+    /// The loadout a player had on the tick that's up next.
+    fn next_loadout(&self, player: Player) -> Option<&Loadout> {
+        self.loadouts
+            .iter()
+            .take_while(|(from_tick, _)| *from_tick <= self.tick)
+            .last()
+            .and_then(|(_, loadouts)| loadouts.get(player.0))
+    }
+}
+
+fn current_loadout(asset_server: &AssetServer, spell_slot_map: &SpellSlotMap) -> Loadout {
+    spell_slot_map
+        .iter()
+        .filter_map(|(&slot, handle)| Some((slot, asset_server.get_path(handle)?.to_string())))
+        .collect()
+}
+
+fn restart_recording(mut recorder: ResMut<Recorder>, replay: Option<ResMut<Replay>>) {
+    recorder.0 = None;
+    if let Some(mut replay) = replay {
+        replay.tick = 0;
+    }
+}
+
+fn record_tick(
+    asset_server: Res<AssetServer>,
+    rng: Res<GameRng>,
+    players: Res<LocalPlayers>,
+    mut recorder: ResMut<Recorder>,
+    mage_query: Query<(&Player, &PlayerInput, &SpellSlotMap), With<Mage>>,
+) {
+    if mage_query.is_empty() {
+        return;
+    }
+
+    // Fallen players stay in the recording, doing nothing.
+    let mut inputs = vec![TickInput::default(); players.0];
+    let mut loadouts = vec![Loadout::default(); players.0];
+    for (player, input, spell_slot_map) in mage_query.iter() {
+        inputs[player.0] = (**input).clone();
+        loadouts[player.0] = current_loadout(&asset_server, spell_slot_map);
+    }
+
+    recorder
+        .0
+        .get_or_insert_with(|| Recording::new(rng.seed(), players.0))
+        .push(&inputs, &loadouts);
+}
+
+fn save_recording(recorder: Res<Recorder>, record_to: Res<RecordTo>) {
+    let Some(recording) = &recorder.0 else {
+        return;
+    };
+
+    match recording.save(&record_to.0) {
+        Ok(()) => info!(
+            "Saved {} ticks of input to {}",
+            recording.len(),
+            record_to.0.display()
+        ),
+        Err(error) => warn!("Couldn't save {}: {error}", record_to.0.display()),
+    }
+}
+
+/// The keyboard and gamepads have no say while a replay is playing.
+fn disconnect_controls(
+    mut commands: Commands,
+    mage_query: Query<Entity, (With<Mage>, With<InputMap<MageActions>>)>,
+) {
+    for entity in mage_query.iter() {
+        commands.entity(entity).remove::<InputMap<MageActions>>();
+    }
+}
+
+/// Presses the mages' actions the way the recording says, so everything reading them sees the
+/// replay rather than the controls.
+fn feed_replayed_actions(
+    replay: Res<Replay>,
+    mut mage_query: Query<(&Player, &mut ActionState<MageActions>), With<Mage>>,
+) {
+    let Some(inputs) = replay.next_inputs() else {
+        return;
+    };
+
+    for (player, mut action_state) in mage_query.iter_mut() {
+        let Some(input) = inputs.get(player.0) else {
+            continue;
+        };
+
+        for action in MageActions::variants() {
+            if input.pressed(action) || input.just_pressed(action) {
+                action_state.press(action);
+            } else {
+                action_state.release(action);
+            }
+        }
+
+        action_state.action_data_mut(MageActions::Move).axis_pair =
+            Some(DualAxisData::from_xy(input.movement));
+        action_state.action_data_mut(MageActions::Aim).axis_pair =
+            Some(DualAxisData::from_xy(input.aim_stick));
+    }
+}
+
+/// Overrides each tick's input with the recorded one, including the parts that don't come from
+/// actions, like where the cursor was. Frames and ticks don't line up the same way twice.
+fn replay_tick(
+    asset_server: Res<AssetServer>,
+    mut replay: ResMut<Replay>,
+    mut mage_query: Query<(&Player, &mut PlayerInput, &mut SpellSlotMap), With<Mage>>,
+) {
+    if mage_query.is_empty() {
+        return;
+    }
+
+    let Some(next_inputs) = replay.next_inputs() else {
+        if replay.tick as usize == replay.inputs.len() {
+            info!("Replay finished after {} ticks", replay.tick);
+            replay.tick += 1;
+        }
+        for (_, mut input, _) in mage_query.iter_mut() {
+            input.set(TickInput::default());
+        }
+        return;
+    };
+
+    for (&player, mut input, mut spell_slot_map) in mage_query.iter_mut() {
+        input.set(next_inputs.get(player.0).cloned().unwrap_or_default());
+
+        // The loadout is swapped back in case the spell wheel or loadout panel changed it
+        // between ticks, where the recorded run didn't.
+        if let Some(loadout) = replay.next_loadout(player) {
+            if current_loadout(&asset_server, &spell_slot_map) != *loadout {
+                let mut replayed_slot_map = SpellSlotMap::default();
+                for (&slot, path) in loadout.iter() {
+                    replayed_slot_map.insert(slot, asset_server.load(path.clone()));
+                }
+                *spell_slot_map = replayed_slot_map;
+            }
+        }
+    }
+
+    replay.tick += 1;
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Recorder>()
+            .add_systems(StartRun, restart_recording);
+
+        if let Some(recording) = &self.replay {
+            app.insert_resource(RunSeed(recording.seed()))
+                .insert_resource(LocalPlayers(recording.players()))
+                .insert_resource(Replay::new(recording))
+                .add_systems(
+                    PreUpdate,
+                    (
+                        disconnect_controls.before(InputManagerSystem::Update),
+                        feed_replayed_actions.in_set(InputManagerSystem::ManualControl),
+                    ),
+                )
+                .add_systems(
+                    FixedUpdate,
+                    replay_tick
+                        .after(TickSet::Input)
+                        .before(TickSet::Act)
+                        .run_if(in_state(GameState::Playing)),
+                );
+        } else {
+            // Online ticks are simulated again after a misprediction, so can't be recorded.
+            app.add_systems(
+                FixedUpdate,
+                record_tick
+                    .after(TickSet::Input)
+                    .before(TickSet::Act)
+                    .run_if(in_state(GameState::Playing).and_then(offline)),
+            );
+
+            if let Some(record_to) = &self.record_to {
+                // Saved whenever play stops, so a crash mid-run still leaves the run up to the
+                // last pause behind.
+                app.insert_resource(RecordTo(record_to.clone()))
+                    .add_systems(OnExit(GameState::Playing), save_recording);
+            }
+        }
+    }
+}
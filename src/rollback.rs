@@ -0,0 +1,258 @@
+use std::any::Any;
+use std::sync::Arc;
+
+use bevy::ecs::system::RunSystemOnce;
+use bevy::hierarchy::despawn_with_children_recursive;
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use bevy_xpbd_2d::prelude::*;
+
+use crate::game_state::StartRun;
+use crate::mage::{Player, MAX_PLAYERS};
+
+pub struct RollbackPlugin;
+
+/// Marks an entity that gameplay can spawn, change or despawn, so a snapshot has to include it.
+/// The id stays the same across rollbacks, unlike the `Entity`, and is handed out in the order
+/// entities are spawned so it's the same on every machine.
+#[derive(Component, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Rollback(u32);
+
+impl Rollback {
+    /// Mages are numbered by player, ahead of everything spawned during the run.
+    pub fn player(player: Player) -> Self {
+        Self(player.0 as u32)
+    }
+}
+
+/// Hands out `Rollback` ids to everything spawned during a run. Reset at the start of each one.
+#[derive(Resource, Clone)]
+pub struct RollbackIds {
+    next: u32,
+}
+
+impl Default for RollbackIds {
+    fn default() -> Self {
+        Self {
+            next: MAX_PLAYERS as u32,
+        }
+    }
+}
+
+impl RollbackIds {
+    pub fn next(&mut self) -> Rollback {
+        let id = Rollback(self.next);
+        self.next += 1;
+        id
+    }
+}
+
+type Saved = Arc<dyn Any + Send + Sync>;
+
+struct ComponentRollback {
+    save: fn(&mut World) -> Saved,
+    load: fn(&mut World, &Saved),
+}
+
+struct ResourceRollback {
+    save: fn(&World) -> Option<Saved>,
+    load: fn(&mut World, Option<&Saved>),
+}
+
+/// Every component and resource that snapshots copy.
+#[derive(Resource, Default)]
+struct RollbackRegistry {
+    components: Vec<ComponentRollback>,
+    resources: Vec<ResourceRollback>,
+}
+
+/// Registers what rollback has to save, next to the plugin that owns it.
+///
+/// A despawned entity is spawned again from its registered components alone, so everything an
+/// entity with `Rollback` needs has to be registered, down to its sprite.
+pub trait RollbackApp {
+    fn rollback_component<T: Component + Clone>(&mut self) -> &mut Self;
+    fn rollback_resource<T: Resource + Clone>(&mut self) -> &mut Self;
+}
+
+impl RollbackApp for App {
+    fn rollback_component<T: Component + Clone>(&mut self) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(RollbackRegistry::default)
+            .components
+            .push(ComponentRollback {
+                save: save_component::<T>,
+                load: load_component::<T>,
+            });
+        self
+    }
+
+    fn rollback_resource<T: Resource + Clone>(&mut self) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(RollbackRegistry::default)
+            .resources
+            .push(ResourceRollback {
+                save: save_resource::<T>,
+                load: load_resource::<T>,
+            });
+        self
+    }
+}
+
+fn save_component<T: Component + Clone>(world: &mut World) -> Saved {
+    let components: Vec<(Entity, T)> = world
+        .query_filtered::<(Entity, &T), With<Rollback>>()
+        .iter(world)
+        .map(|(entity, component)| (entity, component.clone()))
+        .collect();
+    Arc::new(components)
+}
+
+fn load_component<T: Component + Clone>(world: &mut World, saved: &Saved) {
+    let saved = saved
+        .downcast_ref::<Vec<(Entity, T)>>()
+        .expect("snapshot components are saved and loaded by the same registration");
+
+    let mut missing: HashSet<Entity> = world
+        .query_filtered::<Entity, (With<Rollback>, With<T>)>()
+        .iter(world)
+        .collect();
+
+    for (entity, component) in saved {
+        missing.remove(entity);
+        let Some(mut entity) = world.get_entity_mut(*entity) else {
+            continue;
+        };
+
+        // Overwritten in place where possible, so it doesn't count as newly added.
+        match entity.get_mut::<T>() {
+            Some(mut existing) => *existing = component.clone(),
+            None => {
+                entity.insert(component.clone());
+            }
+        }
+    }
+
+    for entity in missing {
+        world.entity_mut(entity).remove::<T>();
+    }
+}
+
+fn save_resource<T: Resource + Clone>(world: &World) -> Option<Saved> {
+    world
+        .get_resource::<T>()
+        .map(|resource| Arc::new(resource.clone()) as Saved)
+}
+
+fn load_resource<T: Resource + Clone>(world: &mut World, saved: Option<&Saved>) {
+    match saved.and_then(|saved| saved.downcast_ref::<T>()) {
+        Some(resource) => world.insert_resource(resource.clone()),
+        None => {
+            world.remove_resource::<T>();
+        }
+    }
+}
+
+/// A copy of the game between two ticks, which can be restored to simulate from there again.
+/// Only what's registered through `RollbackApp` is included.
+#[derive(Clone)]
+pub struct Snapshot {
+    entities: Vec<(Entity, Rollback)>,
+    components: Vec<Saved>,
+    resources: Vec<Option<Saved>>,
+}
+
+impl Snapshot {
+    pub fn take(world: &mut World) -> Self {
+        world.resource_scope(|world, registry: Mut<RollbackRegistry>| {
+            let entities = world
+                .query::<(Entity, &Rollback)>()
+                .iter(world)
+                .map(|(entity, &rollback)| (entity, rollback))
+                .collect();
+
+            Self {
+                entities,
+                components: registry
+                    .components
+                    .iter()
+                    .map(|component| (component.save)(world))
+                    .collect(),
+                resources: registry
+                    .resources
+                    .iter()
+                    .map(|resource| (resource.save)(world))
+                    .collect(),
+            }
+        })
+    }
+
+    pub fn restore(&self, world: &mut World) {
+        // Whatever was spawned since goes first, freeing up the slots of whatever was despawned.
+        let saved: HashSet<Entity> = self.entities.iter().map(|(entity, _)| *entity).collect();
+        let spawned_since: Vec<Entity> = world
+            .query_filtered::<Entity, With<Rollback>>()
+            .iter(world)
+            .filter(|entity| !saved.contains(entity))
+            .collect();
+        for entity in spawned_since {
+            despawn_with_children_recursive(world, entity);
+        }
+
+        for &(entity, rollback) in self.entities.iter() {
+            match world.get_or_spawn(entity) {
+                Some(mut entity) => {
+                    entity.insert(rollback);
+                }
+                None => warn!("Couldn't respawn {rollback:?}, since {entity:?} has been reused"),
+            }
+        }
+
+        world.resource_scope(|world, registry: Mut<RollbackRegistry>| {
+            for (component, saved) in registry.components.iter().zip(&self.components) {
+                (component.load)(world, saved);
+            }
+            for (resource, saved) in registry.resources.iter().zip(&self.resources) {
+                (resource.load)(world, saved.as_ref());
+            }
+        });
+
+        // Character controllers query it before physics rebuilds it for the tick, so it has to
+        // be rebuilt here from the restored bodies rather than left where the rollback started.
+        world.run_system_once(|mut spatial_query: SpatialQuery| spatial_query.update_pipeline());
+    }
+}
+
+fn reset_rollback_ids(mut commands: Commands) {
+    commands.insert_resource(RollbackIds::default());
+}
+
+impl Plugin for RollbackPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RollbackRegistry>()
+            .add_systems(StartRun, reset_rollback_ids)
+            .rollback_resource::<RollbackIds>()
+            // Everything a sprite or a body is made of, which gameplay entities all are.
+            .rollback_component::<Transform>()
+            .rollback_component::<GlobalTransform>()
+            .rollback_component::<Visibility>()
+            .rollback_component::<InheritedVisibility>()
+            .rollback_component::<ViewVisibility>()
+            .rollback_component::<Sprite>()
+            .rollback_component::<TextureAtlasSprite>()
+            .rollback_component::<Handle<Image>>()
+            .rollback_component::<Handle<TextureAtlas>>()
+            .rollback_component::<RigidBody>()
+            .rollback_component::<Collider>()
+            .rollback_component::<Sensor>()
+            .rollback_component::<CollisionLayers>()
+            .rollback_component::<GravityScale>()
+            .rollback_component::<LockedAxes>()
+            .rollback_component::<Position>()
+            .rollback_component::<Rotation>()
+            .rollback_component::<LinearVelocity>()
+            .rollback_component::<AngularVelocity>()
+            // Which contacts are ongoing decides whether collisions start on the next tick.
+            .rollback_resource::<Collisions>();
+    }
+}
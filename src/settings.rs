@@ -0,0 +1,40 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Where bindings and the player profile are read from at startup, and saved to when they
+/// change. Without it, everything starts from its defaults and nothing is saved.
+#[derive(Resource, Clone, Debug)]
+pub struct SettingsDir(pub PathBuf);
+
+impl SettingsDir {
+    pub fn file(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+/// Reads settings saved with `save_ron`. Returns `None` if nothing has been saved yet, or if the
+/// file is invalid, which is warned about rather than treated as an error.
+pub fn load_ron<T: DeserializeOwned>(path: impl AsRef<Path>) -> Option<T> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path).ok()?;
+
+    ron::from_str(&contents)
+        .map_err(|error| warn!("Ignoring invalid {}: {error}", path.display()))
+        .ok()
+}
+
+/// Saves settings as readable RON, warning if that fails.
+pub fn save_ron<T: Serialize>(path: impl AsRef<Path>, value: &T) {
+    let path = path.as_ref();
+    let result = ron::ser::to_string_pretty(value, default())
+        .map_err(|error| error.to_string())
+        .and_then(|contents| fs::write(path, contents).map_err(|error| error.to_string()));
+
+    if let Err(error) = result {
+        warn!("Couldn't save {}: {error}", path.display());
+    }
+}
@@ -0,0 +1,151 @@
+use std::time::Duration;
+
+use bevy::ecs::schedule::ExecutorKind;
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+use bevy_xpbd_2d::plugins::sync::SyncConfig;
+use bevy_xpbd_2d::prelude::*;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::game_state::StartRun;
+use crate::rollback::RollbackApp;
+
+pub struct SimulationPlugin;
+
+/// How much game time every gameplay tick simulates, whatever the frame rate.
+pub const TICK: Duration = Duration::from_nanos(16_666_667);
+
+/// The stages of a gameplay tick in `FixedUpdate`, in the order they run. Physics steps
+/// between `Move` and `React`.
+///
+/// Events sent during a tick have to be read later in the same tick: several frames can
+/// pass between ticks, and events don't survive that long.
+///
+/// Within a set, systems whose order changes what happens, such as anything that draws from
+/// `GameRng` or spawns rollback entities, are ordered with chains or sub-sets like `MageSet` and
+/// `BlastSet`. The order the executor happens to pick mustn't matter.
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TickSet {
+    /// Samples this tick's `PlayerInput`.
+    Input,
+    /// Mages, enemies, spells and animations decide what to do.
+    Act,
+    /// Character controllers carry out the movement decided in `Act`.
+    Move,
+    /// Responds to the collisions physics found.
+    React,
+    /// Deals out the damage from `React`.
+    Damage,
+    /// Removes whatever died or expired this tick.
+    Cleanup,
+}
+
+/// Interpolated transforms are written in this set in `PostUpdate`, so anything following a
+/// body on screen should run after it.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InterpolationSet;
+
+/// Draws a body between the positions of its last two ticks, so that motion stays smooth when
+/// frames don't line up with ticks. This puts what's on screen up to one tick behind.
+///
+/// The `Transform` of an interpolated body is only for rendering. Gameplay reads `Position`.
+#[derive(Component, Clone, Default)]
+pub struct Interpolated {
+    previous: Vec2,
+    current: Option<Vec2>,
+}
+
+/// Seeds the gameplay RNG for every run. Without it, each run gets a fresh random seed.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct RunSeed(pub u64);
+
+/// The only randomness gameplay is allowed, so identical input plays out identically.
+#[derive(Resource, Clone, Deref, DerefMut)]
+pub struct GameRng {
+    seed: u64,
+    #[deref]
+    rng: StdRng,
+}
+
+impl GameRng {
+    /// The seed this run started from.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+fn seed_rng(mut commands: Commands, run_seed: Option<Res<RunSeed>>) {
+    let seed = run_seed.map_or_else(rand::random, |run_seed| run_seed.0);
+    info!("Starting run with seed {seed}");
+
+    commands.insert_resource(GameRng {
+        seed,
+        rng: StdRng::seed_from_u64(seed),
+    });
+}
+
+fn record_positions(mut body_query: Query<(&mut Interpolated, &Position)>) {
+    for (mut interpolated, position) in body_query.iter_mut() {
+        interpolated.previous = interpolated.current.unwrap_or(position.0);
+        interpolated.current = Some(position.0);
+    }
+}
+
+fn interpolate_transforms(
+    fixed_time: Res<Time<Fixed>>,
+    mut body_query: Query<(&Interpolated, &mut Transform)>,
+) {
+    let progress = fixed_time.overstep_percentage();
+
+    for (interpolated, mut transform) in body_query.iter_mut() {
+        // Not simulated yet, so it's still where it was spawned.
+        let Some(current) = interpolated.current else {
+            continue;
+        };
+
+        let position = interpolated.previous.lerp(current, progress);
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+    }
+}
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Time::<Fixed>::from_duration(TICK))
+            // One physics step per tick, rather than physics keeping a clock of its own.
+            .insert_resource(Time::new_with(Physics::fixed_once_hz(
+                1.0 / TICK.as_secs_f64(),
+            )))
+            // Interpolation writes transforms that physics must not read back as teleports.
+            .insert_resource(SyncConfig {
+                position_to_transform: true,
+                transform_to_position: false,
+            })
+            // Ticks are too small to gain from threads. The order doesn't rest on this.
+            .edit_schedule(FixedUpdate, |schedule| {
+                schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+            })
+            .configure_sets(
+                FixedUpdate,
+                (TickSet::Input, TickSet::Act, TickSet::Move)
+                    .chain()
+                    .before(PhysicsSet::Prepare),
+            )
+            .configure_sets(
+                FixedUpdate,
+                (TickSet::React, TickSet::Damage, TickSet::Cleanup)
+                    .chain()
+                    .after(PhysicsSet::Sync),
+            )
+            .configure_sets(
+                PostUpdate,
+                InterpolationSet.before(TransformSystem::TransformPropagate),
+            )
+            .rollback_component::<Interpolated>()
+            .rollback_resource::<GameRng>()
+            .add_systems(StartRun, seed_rng)
+            .add_systems(FixedUpdate, record_positions.in_set(TickSet::Cleanup))
+            .add_systems(PostUpdate, interpolate_transforms.in_set(InterpolationSet));
+    }
+}
@@ -0,0 +1,200 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AsyncReadExt, LoadContext};
+use bevy::prelude::*;
+use bevy::utils::{BoxedFuture, HashMap};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::aseprite::Aseprite;
+use crate::game_state::GameState;
+use crate::mage::MageSet;
+use crate::rollback::RollbackApp;
+use crate::simulation::TickSet;
+
+pub struct SpellPlugin;
+
+/// The behaviour a spell definition drives. Everything tunable lives in the definition.
+#[derive(PartialEq, Eq, Clone, Debug, Hash, Copy, Reflect, Deserialize)]
+pub enum Spell {
+    BlastLaunch,
+    BlastActivate,
+}
+
+#[derive(Asset, TypePath, Debug)]
+pub struct SpellDefinition {
+    pub name: String,
+    pub effect: Spell,
+    #[dependency]
+    pub sprite: Option<Handle<Aseprite>>,
+    pub speed: f32,
+    pub lifetime: f32,
+    pub cooldown: f32,
+    pub damage: f32,
+    pub radius: f32,
+    pub mana_cost: f32,
+}
+
+#[derive(Debug)]
+pub enum CastFailReason {
+    OnCooldown,
+    NotEnoughMana,
+}
+
+/// Sent when a caster tries to cast a spell but isn't allowed to.
+#[derive(Event)]
+pub struct CastFailed {
+    pub caster: Entity,
+    pub spell: Handle<SpellDefinition>,
+    pub reason: CastFailReason,
+}
+
+/// Tracks how long each spell a caster has used still needs before it can be cast again.
+#[derive(Component, Clone, Default)]
+pub struct SpellCooldowns {
+    timers: HashMap<AssetId<SpellDefinition>, Timer>,
+}
+
+impl SpellCooldowns {
+    pub fn is_ready(&self, spell: AssetId<SpellDefinition>) -> bool {
+        self.timers
+            .get(&spell)
+            .map_or(true, |timer| timer.finished())
+    }
+
+    pub fn start(&mut self, spell: AssetId<SpellDefinition>, seconds: f32) {
+        self.timers
+            .insert(spell, Timer::from_seconds(seconds, TimerMode::Once));
+    }
+
+    /// How much of the cooldown is left, from 1.0 right after casting down to 0.0 when ready.
+    pub fn remaining_fraction(&self, spell: AssetId<SpellDefinition>) -> f32 {
+        self.timers
+            .get(&spell)
+            .map_or(0.0, |timer| timer.percent_left())
+    }
+}
+
+/// The on-disk layout of a `.spell.ron` file. Every spell needs a name and an effect, while the
+/// sprite and tuning numbers can be left out. Misspelt fields fail to load rather than being
+/// quietly ignored.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SpellDefinitionRon {
+    name: String,
+    effect: Spell,
+    #[serde(default)]
+    sprite: Option<String>,
+    #[serde(default)]
+    speed: f32,
+    #[serde(default)]
+    lifetime: f32,
+    #[serde(default)]
+    cooldown: f32,
+    #[serde(default)]
+    damage: f32,
+    #[serde(default)]
+    radius: f32,
+    #[serde(default)]
+    mana_cost: f32,
+}
+
+#[derive(Default)]
+struct SpellDefinitionLoader;
+
+#[derive(Error, Debug)]
+enum SpellDefinitionLoaderError {
+    #[error("Could not read spell definition: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse spell definition: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+}
+
+impl AssetLoader for SpellDefinitionLoader {
+    type Asset = SpellDefinition;
+    type Settings = ();
+    type Error = SpellDefinitionLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a Self::Settings,
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let ron: SpellDefinitionRon = ron::de::from_bytes(&bytes)?;
+
+            Ok(SpellDefinition {
+                name: ron.name,
+                effect: ron.effect,
+                sprite: ron.sprite.map(|path| load_context.load(path)),
+                speed: ron.speed,
+                lifetime: ron.lifetime,
+                cooldown: ron.cooldown,
+                damage: ron.damage,
+                radius: ron.radius,
+                mana_cost: ron.mana_cost,
+            })
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["spell.ron"]
+    }
+}
+
+fn tick_spell_cooldowns(time: Res<Time>, mut cooldowns_query: Query<&mut SpellCooldowns>) {
+    for mut cooldowns in cooldowns_query.iter_mut() {
+        for timer in cooldowns.timers.values_mut() {
+            timer.tick(time.delta());
+        }
+    }
+}
+
+fn report_reloaded_spells(
+    mut asset_events: EventReader<AssetEvent<SpellDefinition>>,
+    spells: Res<Assets<SpellDefinition>>,
+) {
+    for event in asset_events.read() {
+        if let AssetEvent::Modified { id } = event {
+            if let Some(spell) = spells.get(*id) {
+                info!(
+                    "Reloaded spell {} (cooldown {}s, {} mana)",
+                    spell.name, spell.cooldown, spell.mana_cost
+                );
+            }
+        }
+    }
+}
+
+fn report_failed_casts(
+    mut cast_failed_events: EventReader<CastFailed>,
+    spells: Res<Assets<SpellDefinition>>,
+) {
+    for cast_failed in cast_failed_events.read() {
+        if let Some(spell) = spells.get(&cast_failed.spell) {
+            debug!(
+                "{:?} couldn't cast {}: {:?}",
+                cast_failed.caster, spell.name, cast_failed.reason
+            );
+        }
+    }
+}
+
+impl Plugin for SpellPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<SpellDefinition>()
+            .init_asset_loader::<SpellDefinitionLoader>()
+            .add_event::<CastFailed>()
+            .rollback_component::<SpellCooldowns>()
+            .add_systems(
+                FixedUpdate,
+                tick_spell_cooldowns
+                    .in_set(TickSet::Act)
+                    .before(MageSet)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(Update, (report_reloaded_spells, report_failed_casts));
+    }
+}
@@ -0,0 +1,179 @@
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::*;
+
+use crate::game_state::{GameState, InGame};
+use crate::loadout::PlayerProfile;
+use crate::mage::{Aim, Mage, MageActions, Player, SpellSlotMap};
+use crate::player_input::{offline, Seating};
+use crate::prelude::TILE_SIZE;
+use crate::spell::SpellDefinition;
+
+pub struct SpellWheelPlugin;
+
+const WHEEL_RADIUS: f32 = TILE_SIZE * 1.5;
+const SELECTED_COLOR: Color = Color::YELLOW;
+const UNSELECTED_COLOR: Color = Color::WHITE;
+
+/// Shown around a mage while its wheel action is held. Releasing swaps the selected slot's
+/// spell into the primary slot.
+#[derive(Component)]
+struct SpellWheel {
+    owner: Entity,
+    selected: Option<MageActions>,
+}
+
+#[derive(Component)]
+struct SpellWheelEntry(MageActions);
+
+/// Entries start at the top and go clockwise, like a clock face.
+fn entry_direction(index: usize) -> Vec2 {
+    let step = TAU / MageActions::WHEEL_SLOTS.len() as f32;
+    Vec2::from_angle(FRAC_PI_2 - index as f32 * step)
+}
+
+/// The name of the spell in one of a mage's slots, or its path while the definition is loading.
+fn spell_label(
+    slot: MageActions,
+    spell_slot_map: &SpellSlotMap,
+    asset_server: &AssetServer,
+    spells: &Assets<SpellDefinition>,
+) -> Option<String> {
+    let handle = spell_slot_map.get(&slot)?;
+    spells
+        .get(handle)
+        .map(|spell| spell.name.clone())
+        .or_else(|| asset_server.get_path(handle).map(|path| path.to_string()))
+}
+
+fn open_spell_wheel(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    spells: Res<Assets<SpellDefinition>>,
+    mage_query: Query<(Entity, &ActionState<MageActions>, &SpellSlotMap, &Transform), With<Mage>>,
+    wheel_query: Query<&SpellWheel>,
+) {
+    for (mage, action_state, spell_slot_map, transform) in mage_query.iter() {
+        if !action_state.just_pressed(MageActions::SpellWheel)
+            || wheel_query.iter().any(|wheel| wheel.owner == mage)
+        {
+            continue;
+        }
+
+        commands
+            .spawn((
+                SpellWheel {
+                    owner: mage,
+                    selected: None,
+                },
+                InGame,
+                SpatialBundle::from_transform(Transform::from_translation(
+                    transform.translation.truncate().extend(10.0),
+                )),
+            ))
+            .with_children(|wheel| {
+                for (index, slot) in MageActions::WHEEL_SLOTS.into_iter().enumerate() {
+                    let label = spell_label(slot, spell_slot_map, &asset_server, &spells)
+                        .unwrap_or_else(|| "-".to_string());
+
+                    wheel.spawn((
+                        SpellWheelEntry(slot),
+                        Text2dBundle {
+                            text: Text::from_section(
+                                label,
+                                TextStyle {
+                                    font_size: 10.0,
+                                    color: UNSELECTED_COLOR,
+                                    ..default()
+                                },
+                            ),
+                            transform: Transform::from_translation(
+                                (entry_direction(index) * WHEEL_RADIUS).extend(0.0),
+                            ),
+                            ..default()
+                        },
+                    ));
+                }
+            });
+    }
+}
+
+fn select_from_spell_wheel(
+    mage_query: Query<(&Aim, &Transform), With<Mage>>,
+    mut wheel_query: Query<(&mut SpellWheel, &mut Transform, &Children), Without<Mage>>,
+    mut entry_query: Query<(&SpellWheelEntry, &mut Text)>,
+) {
+    for (mut wheel, mut transform, children) in wheel_query.iter_mut() {
+        let Ok((aim, mage_transform)) = mage_query.get(wheel.owner) else {
+            continue;
+        };
+
+        transform.translation = mage_transform.translation.truncate().extend(10.0);
+
+        // Whichever entry is closest to where the mage is aiming.
+        wheel.selected = MageActions::WHEEL_SLOTS
+            .into_iter()
+            .enumerate()
+            .max_by(|(a, _), (b, _)| {
+                let a = entry_direction(*a).dot(**aim);
+                let b = entry_direction(*b).dot(**aim);
+                a.total_cmp(&b)
+            })
+            .map(|(_, slot)| slot);
+
+        for &child in children.iter() {
+            if let Ok((entry, mut text)) = entry_query.get_mut(child) {
+                text.sections[0].style.color = if wheel.selected == Some(entry.0) {
+                    SELECTED_COLOR
+                } else {
+                    UNSELECTED_COLOR
+                };
+            }
+        }
+    }
+}
+
+/// The first player's choice is kept in the profile, everyone else's only in their mage.
+fn close_spell_wheel(
+    mut commands: Commands,
+    mut profile: ResMut<PlayerProfile>,
+    seating: Res<Seating>,
+    mut mage_query: Query<(&ActionState<MageActions>, &Player, &mut SpellSlotMap), With<Mage>>,
+    wheel_query: Query<(Entity, &SpellWheel)>,
+) {
+    for (entity, wheel) in wheel_query.iter() {
+        // Fallen mages take their wheel with them.
+        let Ok((action_state, player, mut spell_slot_map)) = mage_query.get_mut(wheel.owner) else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+
+        if !action_state.just_released(MageActions::SpellWheel) {
+            continue;
+        }
+
+        if let Some(selected) = wheel.selected {
+            if seating.seat(*player) == Some(0) {
+                profile.swap_slots(selected, MageActions::SpellPrimary);
+            } else if let Some(spell) = spell_slot_map.remove(&selected) {
+                if let Some(primary) = spell_slot_map.insert(MageActions::SpellPrimary, spell) {
+                    spell_slot_map.insert(selected, primary);
+                }
+            }
+        }
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+impl Plugin for SpellWheelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (open_spell_wheel, select_from_spell_wheel, close_spell_wheel)
+                .chain()
+                // Swapping spells between ticks can't be sent over the network.
+                .run_if(in_state(GameState::Playing).and_then(offline)),
+        );
+    }
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_xpbd_2d::prelude::*;
+use common::HeadlessGame;
+use magic_mania::aseprite::Aseprite;
+use magic_mania::blast::{BlastExploded, LaunchBlast};
+use magic_mania::health::{DamageEvent, Faction, Health, Hurtbox};
+use magic_mania::layers::Layer;
+use magic_mania::mage::{MageActions, SpellSlotMap};
+use magic_mania::spell::SpellDefinition;
+
+fn press(game: &mut HeadlessGame, action: MageActions) {
+    game.input().held.insert(action);
+    game.tick(1);
+    game.input().held.remove(&action);
+}
+
+fn blast_sprite(game: &mut HeadlessGame) -> Handle<Aseprite> {
+    let mage = game.mage();
+    let world = &game.app.world;
+    let launch = &world.get::<SpellSlotMap>(mage).unwrap()[&MageActions::SpellPrimary];
+    let spell = world
+        .resource::<Assets<SpellDefinition>>()
+        .get(launch)
+        .unwrap();
+    spell.sprite.clone().unwrap()
+}
+
+#[test]
+fn detonating_catches_enemies_but_spares_the_caster() {
+    let mut game = HeadlessGame::start();
+    let mage = game.mage();
+    let enemy = game
+        .app
+        .world
+        .spawn((
+            Health::new(2.0),
+            Hurtbox {
+                faction: Faction::Enemy,
+            },
+            TransformBundle::from_transform(Transform::from_translation(
+                (game.mage_position() + Vec2::new(50.0, 0.0)).extend(0.0),
+            )),
+            RigidBody::Dynamic,
+            Collider::ball(8.0),
+            CollisionLayers::new([Layer::Enemy], [Layer::PlayerProjectile]),
+            GravityScale(0.0),
+        ))
+        .id();
+
+    // The blast is still right next to the mage when it goes off.
+    press(&mut game, MageActions::SpellPrimary);
+    game.tick(1);
+    press(&mut game, MageActions::SpellSecondary);
+    game.tick(1);
+
+    let world = &game.app.world;
+    assert_eq!(world.get::<Health>(enemy).unwrap().current, 1.0);
+    assert!(world.get::<LinearVelocity>(enemy).unwrap().x > 0.0);
+    assert_eq!(world.get::<Health>(mage).unwrap().current, 5.0);
+}
+
+#[test]
+fn detonating_leaves_other_blasts_and_walls_alone() {
+    let mut game = HeadlessGame::start();
+    let mage = game.mage();
+    let sprite = blast_sprite(&mut game);
+    let origin = game.mage_position() + Vec2::new(40.0, 0.0);
+
+    // Two blasts parked side by side against a wall, with nothing that can be hurt in range.
+    for offset in [0.0, 10.0] {
+        game.app.world.send_event(LaunchBlast {
+            owner: mage,
+            origin: origin + Vec2::new(offset, 0.0),
+            direction: Vec2::X,
+            speed: 0.0,
+            lifetime: 2.0,
+            sprite: sprite.clone(),
+        });
+    }
+    game.app.world.spawn((
+        TransformBundle::from_transform(Transform::from_translation(
+            (origin + Vec2::new(30.0, 0.0)).extend(0.0),
+        )),
+        RigidBody::Static,
+        Collider::cuboid(8.0, 64.0),
+        CollisionLayers::new([Layer::Wall], [Layer::PlayerProjectile]),
+    ));
+    game.tick(1);
+
+    press(&mut game, MageActions::SpellSecondary);
+
+    let world = &game.app.world;
+    let damage_events = world.resource::<Events<DamageEvent>>();
+    assert_eq!(damage_events.get_reader().read(damage_events).count(), 0);
+    let exploded_events = world.resource::<Events<BlastExploded>>();
+    assert_eq!(
+        exploded_events.get_reader().read(exploded_events).count(),
+        2
+    );
+}
@@ -0,0 +1,279 @@
+use std::time::Duration;
+
+use bevy::asset::RecursiveDependencyLoadState;
+use bevy::input::InputPlugin;
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use bevy::utils::HashSet;
+use bevy::window::ExitCondition;
+use bevy_xpbd_2d::prelude::*;
+use leafwing_input_manager::axislike::DualAxisData;
+use leafwing_input_manager::plugin::InputManagerSystem;
+use leafwing_input_manager::prelude::*;
+use magic_mania::game_state::GameState;
+use magic_mania::mage::{LocalPlayers, Mage, MageActions, Player, SpellSlotMap};
+use magic_mania::netcode::OnlineSession;
+use magic_mania::replay::{Recorder, Recording, ReplayPlugin};
+use magic_mania::simulation::RunSeed;
+use magic_mania::GamePlugins;
+
+/// Every `update` advances the game clock by exactly one gameplay tick.
+pub use magic_mania::simulation::TICK;
+
+/// Every headless run plays out from the same seed.
+const SEED: u64 = 0x5eed;
+
+/// How long to wait on the real clock for assets to load before giving up.
+const LOAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Stands in for a player's controls. Applied to their mage each tick, in place of its
+/// `InputMap`.
+#[derive(Default)]
+pub struct ScriptedInput {
+    pub held: HashSet<MageActions>,
+    pub movement: Vec2,
+}
+
+/// One `ScriptedInput` per player, indexed by `Player`.
+#[derive(Resource)]
+struct ScriptedInputs(Vec<ScriptedInput>);
+
+fn apply_scripted_input(
+    inputs: Res<ScriptedInputs>,
+    mut mage_query: Query<(&Player, &mut ActionState<MageActions>), With<Mage>>,
+) {
+    for (player, mut action_state) in mage_query.iter_mut() {
+        let input = &inputs.0[player.0];
+
+        for action in MageActions::variants() {
+            if input.held.contains(&action) {
+                action_state.press(action);
+            } else {
+                action_state.release(action);
+            }
+        }
+
+        action_state.action_data_mut(MageActions::Move).axis_pair =
+            Some(DualAxisData::from_xy(input.movement));
+    }
+}
+
+/// The whole game running without a window or renderer, one fixed tick at a time.
+pub struct HeadlessGame {
+    pub app: App,
+}
+
+impl HeadlessGame {
+    /// Starts a run and waits until the mage's sprites and spells have loaded.
+    pub fn start() -> Self {
+        Self::start_with(None, 1, None)
+    }
+
+    /// Starts a run with `players` mages sharing the screen.
+    pub fn start_coop(players: usize) -> Self {
+        Self::start_with(None, players, None)
+    }
+
+    /// Starts a run that plays `recording` back, ignoring `ScriptedInput`.
+    pub fn replay(recording: Recording) -> Self {
+        let players = recording.players();
+        Self::start_with(Some(recording), players, None)
+    }
+
+    /// Joins an online run. Only the local player's `ScriptedInput` has any effect, and ticks
+    /// only happen once every machine has joined.
+    pub fn start_online(session: OnlineSession) -> Self {
+        let players = session.players();
+        Self::start_with(None, players, Some(session))
+    }
+
+    fn start_with(
+        replay: Option<Recording>,
+        players: usize,
+        online: Option<OnlineSession>,
+    ) -> Self {
+        let replaying = replay.is_some();
+
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            TransformPlugin,
+            HierarchyPlugin,
+            InputPlugin,
+            AssetPlugin::default(),
+            // Registers the window events gameplay listens to, without opening a window.
+            WindowPlugin {
+                primary_window: None,
+                exit_condition: ExitCondition::DontExit,
+                close_when_requested: false,
+            },
+        ))
+        // Normally registered by the renderer, but the spritesheet loader produces these.
+        .init_asset::<Image>()
+        .init_asset::<TextureAtlas>()
+        .insert_resource(TimeUpdateStrategy::ManualDuration(TICK))
+        // A replay brings its own seed and player count.
+        .insert_resource(RunSeed(SEED))
+        .insert_resource(LocalPlayers(players))
+        .add_plugins(PhysicsPlugins::new(FixedUpdate))
+        .add_plugins(GamePlugins.set(ReplayPlugin {
+            replay,
+            record_to: None,
+        }))
+        .insert_resource(ScriptedInputs(
+            (0..players).map(|_| ScriptedInput::default()).collect(),
+        ));
+
+        if let Some(online) = online {
+            app.insert_resource(online);
+        }
+
+        if !replaying {
+            app.add_systems(
+                PreUpdate,
+                apply_scripted_input.in_set(InputManagerSystem::ManualControl),
+            );
+        }
+
+        let mut game = Self { app };
+        game.set_state(GameState::Playing);
+
+        // Without an input map, leafwing leaves the mages' actions to `ScriptedInput`.
+        let mages: Vec<Entity> = game
+            .app
+            .world
+            .query_filtered::<Entity, With<Mage>>()
+            .iter(&game.app.world)
+            .collect();
+        for mage in mages {
+            game.app
+                .world
+                .entity_mut(mage)
+                .remove::<InputMap<MageActions>>();
+        }
+
+        // Hold the run still while loading, so nothing happens before the test starts.
+        game.set_state(GameState::Paused);
+        game.wait_for_mage_assets();
+        game.set_state(GameState::Playing);
+        game
+    }
+
+    pub fn set_state(&mut self, state: GameState) {
+        self.app
+            .world
+            .resource_mut::<NextState<GameState>>()
+            .set(state);
+        self.app.update();
+    }
+
+    fn wait_for_mage_assets(&mut self) {
+        let mage = self.mage();
+        let mut handles: Vec<UntypedHandle> = self
+            .app
+            .world
+            .get::<SpellSlotMap>(mage)
+            .unwrap()
+            .values()
+            .map(|handle| handle.clone().untyped())
+            .collect();
+        handles.push(
+            self.app
+                .world
+                .get::<Handle<TextureAtlas>>(mage)
+                .unwrap()
+                .clone()
+                .untyped(),
+        );
+
+        let started = std::time::Instant::now();
+        loop {
+            let asset_server = self.app.world.resource::<AssetServer>();
+            let loaded = handles.iter().all(|handle| {
+                asset_server.get_recursive_dependency_load_state(handle.id())
+                    == Some(RecursiveDependencyLoadState::Loaded)
+            });
+
+            if loaded {
+                return;
+            }
+
+            assert!(
+                started.elapsed() < LOAD_TIMEOUT,
+                "Timed out loading the mage's assets"
+            );
+            std::thread::sleep(Duration::from_millis(1));
+            self.app.update();
+        }
+    }
+
+    pub fn tick(&mut self, ticks: u32) {
+        for _ in 0..ticks {
+            self.app.update();
+        }
+    }
+
+    /// Updates without any game time passing, so an online run keeps in touch without
+    /// moving on.
+    pub fn wait(&mut self) {
+        self.app
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::ZERO));
+        self.app.update();
+        self.app
+            .insert_resource(TimeUpdateStrategy::ManualDuration(TICK));
+    }
+
+    /// The last tick an online run has simulated.
+    pub fn online_tick(&self) -> i32 {
+        self.app.world.resource::<OnlineSession>().current_tick()
+    }
+
+    /// Everything played so far this run.
+    pub fn recording(&self) -> Recording {
+        self.app
+            .world
+            .resource::<Recorder>()
+            .recording()
+            .cloned()
+            .unwrap_or_else(|| {
+                let players = self.app.world.resource::<LocalPlayers>();
+                Recording::new(SEED, players.0)
+            })
+    }
+
+    /// The first player's controls.
+    pub fn input(&mut self) -> Mut<ScriptedInput> {
+        self.input_of(Player(0))
+    }
+
+    pub fn input_of(&mut self, player: Player) -> Mut<ScriptedInput> {
+        self.app
+            .world
+            .resource_mut::<ScriptedInputs>()
+            .map_unchanged(|inputs| &mut inputs.0[player.0])
+    }
+
+    /// The first player's mage.
+    pub fn mage(&mut self) -> Entity {
+        self.mage_of(Player(0))
+    }
+
+    pub fn mage_of(&mut self, player: Player) -> Entity {
+        self.app
+            .world
+            .query_filtered::<(Entity, &Player), With<Mage>>()
+            .iter(&self.app.world)
+            .find(|(_, mage_player)| **mage_player == player)
+            .map(|(mage, _)| mage)
+            .unwrap_or_else(|| panic!("{player:?} has no mage"))
+    }
+
+    pub fn mage_position(&mut self) -> Vec2 {
+        self.position_of(Player(0))
+    }
+
+    pub fn position_of(&mut self, player: Player) -> Vec2 {
+        let mage = self.mage_of(player);
+        self.app.world.get::<Position>(mage).unwrap().0
+    }
+}
@@ -0,0 +1,38 @@
+mod common;
+
+use bevy::prelude::*;
+use common::HeadlessGame;
+use magic_mania::mage::Player;
+
+#[test]
+fn every_player_gets_a_mage_of_their_own_colour() {
+    let mut game = HeadlessGame::start_coop(3);
+
+    let tints: Vec<Color> = (0..3)
+        .map(|index| {
+            let mage = game.mage_of(Player(index));
+            game.app
+                .world
+                .get::<TextureAtlasSprite>(mage)
+                .unwrap()
+                .color
+        })
+        .collect();
+
+    assert_ne!(tints[0], tints[1]);
+    assert_ne!(tints[0], tints[2]);
+    assert_ne!(tints[1], tints[2]);
+}
+
+#[test]
+fn players_move_independently() {
+    let mut game = HeadlessGame::start_coop(2);
+    let first_start = game.position_of(Player(0));
+    let second_start = game.position_of(Player(1));
+
+    game.input_of(Player(1)).movement = Vec2::X;
+    game.tick(30);
+
+    assert_eq!(game.position_of(Player(0)), first_start);
+    assert!(game.position_of(Player(1)).x > second_start.x);
+}
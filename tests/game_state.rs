@@ -0,0 +1,43 @@
+mod common;
+
+use common::HeadlessGame;
+use leafwing_input_manager::prelude::*;
+use magic_mania::game_state::OpenPanels;
+use magic_mania::mage::MageActions;
+
+fn actions_enabled(game: &HeadlessGame) -> bool {
+    game.app
+        .world
+        .resource::<ToggleActions<MageActions>>()
+        .enabled
+}
+
+#[test]
+fn actions_stay_off_until_every_panel_is_closed() {
+    let mut game = HeadlessGame::start();
+
+    game.app
+        .world
+        .resource_mut::<OpenPanels>()
+        .set("bindings", true);
+    game.app
+        .world
+        .resource_mut::<OpenPanels>()
+        .set("loadout", true);
+    game.tick(1);
+    assert!(!actions_enabled(&game));
+
+    game.app
+        .world
+        .resource_mut::<OpenPanels>()
+        .set("bindings", false);
+    game.tick(1);
+    assert!(!actions_enabled(&game));
+
+    game.app
+        .world
+        .resource_mut::<OpenPanels>()
+        .set("loadout", false);
+    game.tick(1);
+    assert!(actions_enabled(&game));
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use bevy::prelude::*;
+use common::HeadlessGame;
+use magic_mania::health::{DamageEvent, Damaged, Health, Invulnerable};
+
+fn hit(game: &mut HeadlessGame, target: Entity) {
+    game.app.world.send_event(DamageEvent {
+        target,
+        amount: 1.0,
+        source: None,
+    });
+}
+
+#[test]
+fn hits_in_the_same_tick_only_land_once() {
+    let mut game = HeadlessGame::start();
+    let target = game
+        .app
+        .world
+        .spawn(Health::new(5.0).with_invulnerability(1.0))
+        .id();
+
+    hit(&mut game, target);
+    hit(&mut game, target);
+    game.tick(1);
+
+    assert_eq!(game.app.world.get::<Health>(target).unwrap().current, 4.0);
+}
+
+#[test]
+fn only_hits_that_land_count_as_damage() {
+    let mut game = HeadlessGame::start();
+    let target = game
+        .app
+        .world
+        .spawn(Health::new(5.0).with_invulnerability(1.0))
+        .id();
+
+    let mut reader = game.app.world.resource::<Events<Damaged>>().get_reader();
+    let mut damaged = Vec::new();
+    let mut landed = |game: &mut HeadlessGame| {
+        let damaged_events = game.app.world.resource::<Events<Damaged>>();
+        damaged.extend(
+            reader
+                .read(damaged_events)
+                .filter(|damaged| damaged.entity == target)
+                .map(|damaged| damaged.amount),
+        );
+    };
+
+    hit(&mut game, target);
+    hit(&mut game, target);
+    game.tick(1);
+    landed(&mut game);
+    // Still invulnerable from the first.
+    hit(&mut game, target);
+    game.tick(1);
+    landed(&mut game);
+
+    assert_eq!(damaged, [1.0]);
+}
+
+#[test]
+fn invulnerability_wears_off_without_a_sprite() {
+    let mut game = HeadlessGame::start();
+    let target = game
+        .app
+        .world
+        .spawn(Health::new(5.0).with_invulnerability(1.0))
+        .id();
+
+    hit(&mut game, target);
+    game.tick(1);
+    assert!(game.app.world.get::<Invulnerable>(target).is_some());
+
+    game.tick(70);
+    assert!(game.app.world.get::<Invulnerable>(target).is_none());
+
+    hit(&mut game, target);
+    game.tick(1);
+    assert_eq!(game.app.world.get::<Health>(target).unwrap().current, 3.0);
+}
@@ -0,0 +1,202 @@
+mod common;
+
+use bevy::prelude::*;
+use common::{HeadlessGame, TICK};
+use magic_mania::animation::{AnimationLibrary, SpriteAnimation};
+use magic_mania::blast::Blast;
+use magic_mania::game_state::GameState;
+use magic_mania::health::DamageEvent;
+use magic_mania::mage::{MageActions, SpellSlotMap, MAGE_SPEED};
+use magic_mania::spell::SpellDefinition;
+
+const BLAST_LAUNCH: &str = "spells/blast_launch.spell.ron";
+const BLAST_ACTIVATE: &str = "spells/blast_activate.spell.ron";
+
+fn assign(game: &mut HeadlessGame, slot: MageActions, path: &'static str) {
+    let spell: Handle<SpellDefinition> = game.app.world.resource::<AssetServer>().load(path);
+    let mage = game.mage();
+    game.app
+        .world
+        .get_mut::<SpellSlotMap>(mage)
+        .unwrap()
+        .insert(slot, spell);
+}
+
+fn press(game: &mut HeadlessGame, action: MageActions) {
+    game.input().held.insert(action);
+    game.tick(1);
+    game.input().held.remove(&action);
+}
+
+fn blasts(game: &mut HeadlessGame) -> usize {
+    game.app
+        .world
+        .query_filtered::<(), With<Blast>>()
+        .iter(&game.app.world)
+        .count()
+}
+
+/// How many ticks the mage's `name` clip takes to play through once.
+fn clip_ticks(game: &mut HeadlessGame, name: &str) -> u32 {
+    let mage = game.mage();
+    let world = &game.app.world;
+    let library = world.get::<SpriteAnimation>(mage).unwrap().library();
+    let library = world
+        .resource::<Assets<AnimationLibrary>>()
+        .get(library)
+        .unwrap();
+    (library.clips[name].duration() / TICK.as_secs_f32()).ceil() as u32
+}
+
+#[test]
+fn holding_move_walks_at_mage_speed() {
+    let mut game = HeadlessGame::start();
+    let start = game.mage_position();
+
+    game.input().movement = Vec2::X;
+    game.tick(60);
+
+    let expected = MAGE_SPEED * TICK.as_secs_f32() * 60.0;
+    let moved = game.mage_position() - start;
+    assert!(
+        (moved.x - expected).abs() < 1e-3,
+        "moved {moved}, expected {expected} along x"
+    );
+    assert!(moved.y.abs() < 1e-3, "drifted {} along y", moved.y);
+}
+
+#[test]
+fn diagonal_movement_is_not_faster() {
+    let mut game = HeadlessGame::start();
+    let start = game.mage_position();
+
+    game.input().movement = Vec2::ONE;
+    game.tick(60);
+
+    let expected = MAGE_SPEED * TICK.as_secs_f32() * 60.0;
+    let moved = game.mage_position().distance(start);
+    assert!(
+        (moved - expected).abs() < 1e-3,
+        "moved {moved}, expected {expected}"
+    );
+}
+
+#[test]
+fn casting_locks_movement_until_the_cast_finishes() {
+    let mut game = HeadlessGame::start();
+    let start = game.mage_position();
+
+    game.input().movement = Vec2::X;
+    game.input().held.insert(MageActions::SpellPrimary);
+    game.tick(1);
+    game.input().held.remove(&MageActions::SpellPrimary);
+    game.tick(10);
+
+    assert_eq!(game.mage_position(), start, "moved while casting");
+
+    let cast_ticks = clip_ticks(&mut game, "cast");
+    game.tick(cast_ticks.saturating_sub(10) + 2);
+    assert!(
+        game.mage_position().x > start.x,
+        "still stuck after the cast finished"
+    );
+}
+
+#[test]
+fn a_spell_in_two_slots_casts_from_either() {
+    let mut game = HeadlessGame::start();
+    assign(&mut game, MageActions::SpellPrimary, BLAST_LAUNCH);
+    assign(&mut game, MageActions::SpellSlot1, BLAST_LAUNCH);
+
+    press(&mut game, MageActions::SpellPrimary);
+    assert_eq!(blasts(&mut game), 1);
+
+    // Past the half second cooldown, and the cast animation however long it lasts.
+    let wait = clip_ticks(&mut game, "cast").max(30) + 2;
+    game.tick(wait);
+    press(&mut game, MageActions::SpellSlot1);
+    assert_eq!(blasts(&mut game), 2);
+}
+
+#[test]
+fn every_numbered_slot_casts_the_spell_it_holds() {
+    let mut game = HeadlessGame::start();
+    let wait = clip_ticks(&mut game, "cast").max(30) + 2;
+
+    for slot in MageActions::WHEEL_SLOTS {
+        assign(&mut game, slot, BLAST_LAUNCH);
+        let before = blasts(&mut game);
+        press(&mut game, slot);
+        assert_eq!(blasts(&mut game), before + 1, "{slot:?} didn't cast");
+
+        game.tick(wait);
+    }
+}
+
+#[test]
+fn the_spell_wheel_swaps_a_numbered_slot_into_the_primary_slot() {
+    let mut game = HeadlessGame::start();
+    let activate: Handle<SpellDefinition> = game
+        .app
+        .world
+        .resource::<AssetServer>()
+        .load(BLAST_ACTIVATE);
+
+    // The mage starts out aiming right, at the second entry round the wheel.
+    game.input().held.insert(MageActions::SpellWheel);
+    game.tick(2);
+    game.input().held.remove(&MageActions::SpellWheel);
+    game.tick(2);
+
+    let mage = game.mage();
+    let spell_slot_map = game.app.world.get::<SpellSlotMap>(mage).unwrap();
+    assert_eq!(
+        spell_slot_map.get(&MageActions::SpellPrimary),
+        Some(&activate)
+    );
+}
+
+fn clip(game: &mut HeadlessGame) -> String {
+    let mage = game.mage();
+    game.app
+        .world
+        .get::<SpriteAnimation>(mage)
+        .unwrap()
+        .clip()
+        .to_string()
+}
+
+#[test]
+fn a_fallen_mage_plays_out_its_death_before_the_run_ends() {
+    let mut game = HeadlessGame::start();
+    let mage = game.mage();
+
+    game.app.world.send_event(DamageEvent {
+        target: mage,
+        amount: 1.0,
+        source: None,
+    });
+    game.tick(2);
+    assert_eq!(clip(&mut game), "hurt");
+
+    // Long enough for the invulnerability to wear off.
+    game.tick(70);
+    game.app.world.send_event(DamageEvent {
+        target: mage,
+        amount: 10.0,
+        source: None,
+    });
+    game.tick(2);
+    assert_eq!(clip(&mut game), "die");
+    assert_eq!(
+        *game.app.world.resource::<State<GameState>>().get(),
+        GameState::Playing
+    );
+
+    let die_ticks = clip_ticks(&mut game, "die");
+    game.tick(die_ticks + 1);
+    assert_eq!(
+        *game.app.world.resource::<State<GameState>>().get(),
+        GameState::GameOver
+    );
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use std::collections::BTreeSet;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use bevy_xpbd_2d::prelude::*;
+use common::HeadlessGame;
+use magic_mania::enemy::Bat;
+use magic_mania::mage::{MageActions, Player};
+use magic_mania::netcode::{NetInput, OnlineSession, SimulatedNetwork, UdpChannel};
+use magic_mania::player_input::TickInput;
+
+/// How long to wait on the real clock for both games to get through the script.
+const PLAY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Two games talking to each other over loopback, through a connection that's slow, jittery
+/// and loses a tenth of everything sent.
+fn connect_over_bad_network() -> [HeadlessGame; 2] {
+    let localhost = SocketAddr::from(([127, 0, 0, 1], 0));
+    let sockets = [
+        UdpChannel::bind(localhost).unwrap(),
+        UdpChannel::bind(localhost).unwrap(),
+    ];
+    let addresses = [
+        sockets[0].local_addr().unwrap(),
+        sockets[1].local_addr().unwrap(),
+    ];
+
+    let [first, second] = sockets.map(|socket| {
+        SimulatedNetwork::new(socket)
+            .with_latency(Duration::from_millis(30), Duration::from_millis(10))
+            .with_loss(0.1)
+    });
+    [
+        HeadlessGame::start_online(
+            OnlineSession::start(Player(0), &[addresses[1]], first).unwrap(),
+        ),
+        HeadlessGame::start_online(
+            OnlineSession::start(Player(1), &[addresses[0]], second).unwrap(),
+        ),
+    ]
+}
+
+/// Ticks both games until they've each simulated up to `tick`, keeping whichever gets there
+/// first in touch until the other catches up.
+fn play_until(games: &mut [HeadlessGame; 2], tick: i32) {
+    let started = Instant::now();
+    while games.iter().any(|game| game.online_tick() < tick) {
+        for game in games.iter_mut() {
+            if game.online_tick() < tick {
+                game.tick(1);
+            } else {
+                game.wait();
+            }
+        }
+
+        assert!(
+            started.elapsed() < PLAY_TIMEOUT,
+            "Timed out playing to tick {tick}"
+        );
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+fn bat_positions(game: &mut HeadlessGame) -> Vec<Vec2> {
+    let mut positions: Vec<Vec2> = game
+        .app
+        .world
+        .query_filtered::<&Position, With<Bat>>()
+        .iter(&game.app.world)
+        .map(|position| position.0)
+        .collect();
+    positions.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+    positions
+}
+
+#[test]
+fn both_machines_agree_despite_a_bad_connection() {
+    let mut games = connect_over_bad_network();
+    let starts = [
+        games[0].position_of(Player(0)),
+        games[0].position_of(Player(1)),
+    ];
+
+    games[0].input_of(Player(0)).movement = Vec2::Y;
+    games[1].input_of(Player(1)).movement = Vec2::NEG_X;
+    play_until(&mut games, 60);
+
+    // Standing still for long enough that nobody's last guess about the other can be wrong,
+    // and for the first bat to arrive.
+    games[0].input_of(Player(0)).movement = Vec2::ZERO;
+    games[1].input_of(Player(1)).movement = Vec2::ZERO;
+    play_until(&mut games, 240);
+
+    for player in [Player(0), Player(1)] {
+        assert_eq!(
+            games[0].position_of(player),
+            games[1].position_of(player),
+            "{player:?} ended up in different places"
+        );
+    }
+    assert!(games[0].position_of(Player(0)).y > starts[0].y);
+    assert!(games[0].position_of(Player(1)).x < starts[1].x);
+
+    let [first, second] = &mut games;
+    let bats = bat_positions(first);
+    assert!(!bats.is_empty(), "no bats spawned to compare");
+    assert_eq!(bats, bat_positions(second));
+}
+
+#[test]
+fn packed_input_keeps_actions_and_full_tilt_sticks() {
+    let input = TickInput {
+        movement: Vec2::new(-1.0, 0.0),
+        aim_stick: Vec2::new(0.0, 1.0),
+        cursor: Some(Vec2::new(12.0, -40.0)),
+        cursor_moved: true,
+        pressed: BTreeSet::from([MageActions::SpellPrimary, MageActions::Move]),
+        just_pressed: BTreeSet::from([MageActions::SpellPrimary]),
+    };
+
+    assert_eq!(TickInput::from(NetInput::from(&input)), input);
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use bevy::prelude::*;
+use common::HeadlessGame;
+use magic_mania::mage::{MageActions, Player};
+use magic_mania::replay::Recording;
+
+#[test]
+fn replaying_a_recording_ends_up_in_the_same_place() {
+    let mut recorded = HeadlessGame::start();
+    recorded.input().movement = Vec2::new(1.0, 0.5);
+    recorded.tick(30);
+    recorded.input().held.insert(MageActions::SpellPrimary);
+    recorded.tick(1);
+    recorded.input().held.clear();
+    recorded.tick(40);
+    recorded.input().movement = Vec2::NEG_Y;
+    recorded.tick(30);
+
+    let recording = recorded.recording();
+    let mut replayed = HeadlessGame::replay(recording.clone());
+    replayed.tick(recording.len());
+
+    assert_eq!(replayed.mage_position(), recorded.mage_position());
+}
+
+#[test]
+fn recordings_survive_a_round_trip_through_a_file() {
+    let mut game = HeadlessGame::start();
+    game.input().movement = Vec2::X;
+    game.tick(20);
+    let recording = game.recording();
+
+    let path = std::env::temp_dir().join(format!("magic_mania_{}.replay", std::process::id()));
+    recording.save(&path).unwrap();
+    let loaded = Recording::load(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.unwrap(), recording);
+}
+
+#[test]
+fn replays_keep_every_player_apart() {
+    let mut recorded = HeadlessGame::start_coop(2);
+    recorded.input_of(Player(0)).movement = Vec2::Y;
+    recorded.input_of(Player(1)).movement = Vec2::NEG_X;
+    recorded.tick(30);
+
+    let recording = recorded.recording();
+    assert_eq!(recording.players(), 2);
+    let mut replayed = HeadlessGame::replay(recording.clone());
+    replayed.tick(recording.len());
+
+    for player in [Player(0), Player(1)] {
+        assert_eq!(replayed.position_of(player), recorded.position_of(player));
+    }
+}
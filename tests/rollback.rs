@@ -0,0 +1,215 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_xpbd_2d::prelude::*;
+use common::HeadlessGame;
+use magic_mania::enemy::Bat;
+use magic_mania::health::{DamageEvent, Faction, Health, Hurtbox};
+use magic_mania::layers::Layer;
+use magic_mania::mage::{MageActions, Player};
+use magic_mania::mana::ManaPickup;
+use magic_mania::player_input::PendingInput;
+use magic_mania::rollback::{Rollback, RollbackIds, Snapshot};
+
+fn press(game: &mut HeadlessGame, action: MageActions) {
+    game.input().held.insert(action);
+    game.tick(1);
+    game.input().held.remove(&action);
+}
+
+/// Spawns a body the way gameplay would, so snapshots include it.
+fn spawn_rolled_back(game: &mut HeadlessGame, offset: Vec2, bundle: impl Bundle) -> Entity {
+    let id = game.app.world.resource_mut::<RollbackIds>().next();
+    let position = game.mage_position() + offset;
+    game.app
+        .world
+        .spawn((
+            id,
+            bundle,
+            TransformBundle::from_transform(Transform::from_translation(position.extend(0.0))),
+        ))
+        .id()
+}
+
+fn spawn_enemy(game: &mut HeadlessGame) -> Entity {
+    spawn_rolled_back(
+        game,
+        Vec2::new(50.0, 0.0),
+        (
+            Health::new(2.0),
+            Hurtbox {
+                faction: Faction::Enemy,
+            },
+            RigidBody::Dynamic,
+            Collider::ball(8.0),
+            CollisionLayers::new([Layer::Enemy], [Layer::PlayerProjectile]),
+            GravityScale(0.0),
+        ),
+    )
+}
+
+/// Plays until two bats have flown in, with a mage that can't die in the meantime.
+fn start_with_two_bats() -> HeadlessGame {
+    let mut game = HeadlessGame::start();
+    let mage = game.mage();
+    game.app.world.get_mut::<Health>(mage).unwrap().current = 1000.0;
+
+    for _ in 0..600 {
+        if bats(&mut game).len() >= 2 {
+            return game;
+        }
+        game.tick(1);
+    }
+    panic!("two bats never spawned");
+}
+
+fn bats(game: &mut HeadlessGame) -> Vec<(Rollback, Entity)> {
+    let mut bats: Vec<_> = game
+        .app
+        .world
+        .query_filtered::<(&Rollback, Entity), With<Bat>>()
+        .iter(&game.app.world)
+        .map(|(rollback, entity)| (*rollback, entity))
+        .collect();
+    bats.sort();
+    bats
+}
+
+fn mana_pickups(game: &mut HeadlessGame) -> Vec<(Rollback, Vec2)> {
+    let mut pickups: Vec<_> = game
+        .app
+        .world
+        .query_filtered::<(&Rollback, &Position), With<ManaPickup>>()
+        .iter(&game.app.world)
+        .map(|(rollback, position)| (*rollback, position.0))
+        .collect();
+    pickups.sort_by_key(|(rollback, _)| *rollback);
+    pickups
+}
+
+fn kill(game: &mut HeadlessGame, target: Entity) {
+    game.app.world.send_event(DamageEvent {
+        target,
+        amount: 1000.0,
+        source: None,
+    });
+}
+
+#[test]
+fn walls_from_before_a_rollback_dont_get_in_the_way() {
+    let mut reference = HeadlessGame::start();
+    let mut game = HeadlessGame::start();
+
+    // A wall right in front of the mage, which the rollback takes away again.
+    let snapshot = Snapshot::take(&mut game.app.world);
+    let wall = spawn_rolled_back(
+        &mut game,
+        Vec2::new(12.1, 0.0),
+        (
+            RigidBody::Static,
+            Collider::cuboid(8.0, 32.0),
+            CollisionLayers::new([Layer::Wall], [Layer::Player]),
+        ),
+    );
+    game.tick(1);
+    snapshot.restore(&mut game.app.world);
+    assert!(game.app.world.get_entity(wall).is_none());
+
+    for game in [&mut reference, &mut game] {
+        game.input().movement = Vec2::X;
+        game.tick(1);
+    }
+
+    assert_eq!(game.mage_position(), reference.mage_position());
+}
+
+#[test]
+fn detonating_after_a_rollback_catches_whatever_was_restored_in_range() {
+    let mut reference = HeadlessGame::start();
+    let mut game = HeadlessGame::start();
+    let enemies = [spawn_enemy(&mut reference), spawn_enemy(&mut game)];
+
+    for game in [&mut reference, &mut game] {
+        press(game, MageActions::SpellPrimary);
+        game.tick(1);
+    }
+
+    // Knocked well out of range, then rolled back to where it was.
+    let snapshot = Snapshot::take(&mut game.app.world);
+    game.app.world.get_mut::<Position>(enemies[1]).unwrap().0.x += 500.0;
+    game.tick(1);
+    snapshot.restore(&mut game.app.world);
+
+    for game in [&mut reference, &mut game] {
+        press(game, MageActions::SpellSecondary);
+    }
+
+    for (game, enemy) in [&reference, &game].into_iter().zip(enemies) {
+        assert_eq!(game.app.world.get::<Health>(enemy).unwrap().current, 1.0);
+    }
+    assert_eq!(
+        game.app.world.get::<Position>(enemies[1]).unwrap().0,
+        reference.app.world.get::<Position>(enemies[0]).unwrap().0
+    );
+}
+
+#[test]
+fn bats_dying_together_drop_the_same_mana_after_a_rollback() {
+    let mut reference = start_with_two_bats();
+    let mut game = start_with_two_bats();
+
+    // Rolling back respawns the bat that died behind the one that didn't, so the bats no longer
+    // sit in the order they spawned.
+    let snapshot = Snapshot::take(&mut game.app.world);
+    let first = game
+        .app
+        .world
+        .query_filtered::<Entity, With<Bat>>()
+        .iter(&game.app.world)
+        .next()
+        .unwrap();
+    kill(&mut game, first);
+    game.tick(1);
+    snapshot.restore(&mut game.app.world);
+    let ids =
+        |bats: Vec<(Rollback, Entity)>| bats.into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+    assert_eq!(ids(bats(&mut game)), ids(bats(&mut reference)));
+
+    for game in [&mut reference, &mut game] {
+        let bats = bats(game);
+        for &(_, bat) in bats.iter() {
+            kill(game, bat);
+        }
+        game.tick(1);
+        for (_, bat) in bats {
+            assert!(game.app.world.get_entity(bat).is_none());
+        }
+    }
+
+    assert_eq!(mana_pickups(&mut game), mana_pickups(&mut reference));
+}
+
+#[test]
+fn mages_brought_back_by_a_rollback_can_still_be_played() {
+    let mut game = HeadlessGame::start_coop(2);
+    let fallen = game.mage_of(Player(1));
+
+    // One mage falls and leaves the arena while the other plays on, then it's all taken back.
+    let snapshot = Snapshot::take(&mut game.app.world);
+    kill(&mut game, fallen);
+    for _ in 0..300 {
+        if game.app.world.get_entity(fallen).is_none() {
+            break;
+        }
+        game.tick(1);
+    }
+    assert!(game.app.world.get_entity(fallen).is_none());
+    snapshot.restore(&mut game.app.world);
+
+    let start = game.position_of(Player(1));
+    game.input_of(Player(1)).movement = Vec2::X;
+    game.tick(10);
+
+    assert!(game.app.world.get::<PendingInput>(fallen).is_some());
+    assert!(game.position_of(Player(1)).x > start.x);
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_xpbd_2d::prelude::*;
+use common::HeadlessGame;
+use magic_mania::enemy::Bat;
+use magic_mania::mage::MageActions;
+
+/// Walks in a square, casting along the way, for long enough that bats spawn and give chase.
+fn play_script(game: &mut HeadlessGame) {
+    for (step, direction) in [Vec2::X, Vec2::Y, Vec2::NEG_X, Vec2::NEG_Y]
+        .into_iter()
+        .cycle()
+        .take(12)
+        .enumerate()
+    {
+        game.input().movement = direction;
+        game.tick(20);
+
+        if step % 3 == 0 {
+            game.input().held.insert(MageActions::SpellPrimary);
+            game.tick(1);
+            game.input().held.remove(&MageActions::SpellPrimary);
+        }
+    }
+    game.input().movement = Vec2::ZERO;
+    game.tick(60);
+}
+
+fn bat_positions(game: &mut HeadlessGame) -> Vec<Vec2> {
+    game.app
+        .world
+        .query_filtered::<&Position, With<Bat>>()
+        .iter(&game.app.world)
+        .map(|position| position.0)
+        .collect()
+}
+
+#[test]
+fn identical_input_plays_out_identically() {
+    let mut first = HeadlessGame::start();
+    let mut second = HeadlessGame::start();
+
+    play_script(&mut first);
+    play_script(&mut second);
+
+    let bats = bat_positions(&mut first);
+    assert!(!bats.is_empty(), "no bats spawned to compare");
+    assert_eq!(bats, bat_positions(&mut second));
+    assert_eq!(first.mage_position(), second.mage_position());
+}